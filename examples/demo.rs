@@ -0,0 +1,222 @@
+// Demo of the crate: a forest background under a row of grid sprites, panned with an RTS
+// camera controller, space to shake, a graded minimap in the top right corner. L toggles the
+// LUT passes, C the composite of the game camera.
+// `--headless <width>x<height>`, `--hdr`, `--pixel-perfect` and `--fit-inside` try the
+// output options, headless runs save a frame to `--output <path>` (headless.png) and exit.
+use std::time::Duration;
+
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    prelude::*,
+    render::view::screenshot::{Screenshot, ScreenshotCaptured, save_to_disk},
+    window::{ExitCondition, PresentMode, WindowMode, WindowResolution, WindowTheme},
+    winit::WinitPlugin,
+};
+use bevy_background_camera::BackgroundCameraPlugins;
+use bevy_background_camera::cameras::{
+    background_fit::BackgroundFit,
+    background_lut::{BackgroundLutPassSettings, desaturated_lut_image},
+    camera_plugin::{
+        CameraBounds, GameCamera, HdrComposite, OffscreenOutput, PixelPerfect, RenderOutput,
+        RtsCameraController, ScalingPolicy, ViewportScaling,
+    },
+    camera_shake::CameraShake,
+    composite_pass::{CompositePassSettings, LetterboxFill},
+    layer_registry::{BACKGROUND_LAYER, GAME_LAYER, LayerRegistry},
+    minimap::{Minimap, MinimapDisplay},
+};
+
+// Area the grid lives in, the camera and the minimap stay inside it
+const MAP_BOUNDS: Rect = Rect {
+    min: Vec2::new(-1920.0, -1080.0),
+    max: Vec2::new(1920.0, 1080.0),
+};
+
+// Parses `--headless <width>x<height>` from the command line
+fn headless_size() -> Option<UVec2> {
+    let mut args = std::env::args()
+        .skip_while(|arg| arg != "--headless")
+        .skip(1);
+    let size = args.next()?;
+    let (width, height) = size.split_once('x')?;
+    Some(UVec2::new(width.parse().ok()?, height.parse().ok()?))
+}
+
+// Frames rendered before the headless capture, so the assets have time to load
+const HEADLESS_CAPTURE_FRAME: u32 = 60;
+
+// Where `--output <path>` asks the headless frame to be saved
+fn headless_output_path() -> String {
+    std::env::args()
+        .skip_while(|arg| arg != "--output")
+        .nth(1)
+        .unwrap_or_else(|| "headless.png".into())
+}
+
+fn main() {
+    let mut app = App::new();
+    match headless_size() {
+        // Render into an offscreen image without creating a window or an event loop
+        Some(size) => {
+            app.insert_resource(RenderOutput::Image { size })
+                .add_plugins((
+                    DefaultPlugins
+                        .set(WindowPlugin {
+                            primary_window: None,
+                            exit_condition: ExitCondition::DontExit,
+                            ..default()
+                        })
+                        .disable::<WinitPlugin>(),
+                    ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / 60.0)),
+                ))
+                .add_systems(Update, capture_headless_frame);
+        }
+        None => {
+            app.add_plugins(DefaultPlugins.set({
+                WindowPlugin {
+                    primary_window: Some(Window {
+                        title: "I am a window!".into(),
+                        name: Some("bevy.app".into()),
+                        present_mode: PresentMode::AutoVsync,
+                        mode: WindowMode::Windowed,
+                        resolution: WindowResolution::new(1280., 800.),
+                        window_theme: Some(WindowTheme::Dark),
+                        visible: true,
+                        ..default()
+                    }),
+                    ..default()
+                }
+            }));
+        }
+    }
+    // `--hdr` composites in HDR, with bloom and tonemapping applied to the combined frame
+    if std::env::args().any(|arg| arg == "--hdr") {
+        app.insert_resource(HdrComposite::default());
+    }
+    // `--pixel-perfect` renders the game at 480x270, one world unit per pixel, and upscales it
+    if std::env::args().any(|arg| arg == "--pixel-perfect") {
+        let pixel_perfect = PixelPerfect::default();
+        app.insert_resource(ViewportScaling {
+            reference: pixel_perfect.resolution.as_vec2(),
+            ..default()
+        })
+        .insert_resource(pixel_perfect);
+    }
+    // `--fit-inside` keeps exactly the 16:9 reference area visible, with blurred bars around it
+    if std::env::args().any(|arg| arg == "--fit-inside") {
+        app.insert_resource(ViewportScaling {
+            policy: ScalingPolicy::FitInside {
+                letterbox: LetterboxFill::BlurredBackground { radius: 24.0 },
+            },
+            ..default()
+        });
+    }
+    app.add_plugins(BackgroundCameraPlugins)
+        .add_systems(Startup, (setup_scenery, setup_minimap))
+        .add_systems(Update, (control_game_camera, shake_on_space, toggle_passes))
+        .run();
+}
+
+fn setup_scenery(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    layers: Res<LayerRegistry>,
+) {
+    commands.spawn((
+        Sprite::from_image(asset_server.load("forrest_wqhd.png")),
+        BackgroundFit::Cover,
+        layers.render_layers(BACKGROUND_LAYER),
+    ));
+    for num in 0..20 {
+        commands.spawn((
+            Sprite::from_image(asset_server.load("grid-outline.png")),
+            Transform::from_xyz(-1000. + 100. * num as f32, 0., 0.),
+            layers.render_layers(GAME_LAYER),
+        ));
+    }
+}
+
+// Minimap of the whole map, desaturated for a tactical look
+fn setup_minimap(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let minimap = commands
+        .spawn(
+            Minimap::new(UVec2::new(256, 144), MAP_BOUNDS)
+                .with_lut(images.add(desaturated_lut_image(0.8))),
+        )
+        .id();
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.0),
+            right: Val::Px(12.0),
+            width: Val::Px(256.0),
+            height: Val::Px(144.0),
+            ..default()
+        },
+        MinimapDisplay { minimap },
+    ));
+}
+
+// Reads the offscreen output back once, saves it and exits when it is written
+fn capture_headless_frame(
+    mut commands: Commands,
+    mut frame: Local<u32>,
+    output: Option<Res<OffscreenOutput>>,
+) {
+    *frame += 1;
+    let Some(output) = output.filter(|_| *frame == HEADLESS_CAPTURE_FRAME) else {
+        return;
+    };
+    let path = headless_output_path();
+    info!("Saving the offscreen output to {}", path);
+    commands
+        .spawn(Screenshot::image(output.handle.clone()))
+        .observe(save_to_disk(path))
+        .observe(
+            |_: Trigger<ScreenshotCaptured>, mut exit: EventWriter<AppExit>| {
+                exit.send(AppExit::Success);
+            },
+        );
+}
+
+// The game camera is spawned by the plugins, the controls are added once it exists
+fn control_game_camera(mut commands: Commands, game_cameras: Query<Entity, Added<GameCamera>>) {
+    for entity in game_cameras.iter() {
+        commands.entity(entity).insert((
+            RtsCameraController {
+                bounds: Some(CameraBounds::Rect(MAP_BOUNDS)),
+                ..default()
+            },
+            CameraShake::default(),
+        ));
+    }
+}
+
+// Every press of space is an impact
+fn shake_on_space(keys: Res<ButtonInput<KeyCode>>, mut shakes: Query<&mut CameraShake>) {
+    if keys.just_pressed(KeyCode::Space) {
+        for mut shake in shakes.iter_mut() {
+            shake.add_trauma(0.5);
+        }
+    }
+}
+
+// Switches the passes at runtime, the cameras and pipelines stay as they are
+fn toggle_passes(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut lut_passes: Query<&mut BackgroundLutPassSettings>,
+    mut composite_passes: Query<&mut CompositePassSettings, With<GameCamera>>,
+) {
+    if keys.just_pressed(KeyCode::KeyL) {
+        for mut settings in lut_passes.iter_mut() {
+            settings.enabled = !settings.enabled;
+            info!("LUT pass enabled: {}", settings.enabled);
+        }
+    }
+    if keys.just_pressed(KeyCode::KeyC) {
+        for mut settings in composite_passes.iter_mut() {
+            settings.enabled = !settings.enabled;
+            info!("Composite pass enabled: {}", settings.enabled);
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use super::layer_registry::{BACKGROUND_LAYER, LayerRegistry};
+
+// Where the frames of an animated background come from
+#[derive(Clone, Debug)]
+pub enum BackgroundFrames {
+    // Frames packed into a sprite sheet, played from atlas index `first` to `last` (inclusive)
+    Atlas {
+        image: Handle<Image>,
+        layout: Handle<TextureAtlasLayout>,
+        first: usize,
+        last: usize,
+    },
+    // Numbered image files, e.g. `path_pattern: "sunrise/frame_{}.png"` with `digits: 4`
+    // loads `sunrise/frame_0000.png` onwards. Only the current frame and the next `preload`
+    // frames (at least the next one) are kept loaded, the rest is streamed in and dropped
+    // while playing, so long full-resolution sequences never sit in VRAM all at once.
+    Sequence {
+        path_pattern: String,
+        first: usize,
+        count: usize,
+        digits: usize,
+        preload: usize,
+    },
+}
+
+impl BackgroundFrames {
+    fn len(&self) -> usize {
+        match self {
+            BackgroundFrames::Atlas { first, last, .. } => last.saturating_sub(*first) + 1,
+            BackgroundFrames::Sequence { count, .. } => *count,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PlaybackMode {
+    // Restart from the first frame after the last one
+    #[default]
+    Loop,
+    // Play forward, then backward, then forward again
+    PingPong,
+    // Play once and stop on the last frame
+    Once,
+}
+
+// Plays a frame animation on the sprite of this entity, on the background layer
+#[derive(Component, Clone, Debug)]
+#[require(Sprite)]
+pub struct AnimatedBackground {
+    pub frames: BackgroundFrames,
+    pub fps: f32,
+    pub mode: PlaybackMode,
+    pub playing: bool,
+}
+
+impl AnimatedBackground {
+    pub fn new(frames: BackgroundFrames, fps: f32, mode: PlaybackMode) -> Self {
+        Self {
+            frames,
+            fps,
+            mode,
+            playing: true,
+        }
+    }
+}
+
+// Sent when an animation in `PlaybackMode::Once` has shown its last frame for a frame's duration
+#[derive(Event, Clone, Copy, Debug)]
+pub struct BackgroundAnimationFinished {
+    pub entity: Entity,
+}
+
+// Sent each time a looping or ping-pong animation completes a full cycle
+#[derive(Event, Clone, Copy, Debug)]
+pub struct BackgroundAnimationLooped {
+    pub entity: Entity,
+}
+
+// Runtime playback state of an animated background
+#[derive(Component, Default)]
+struct AnimationPlayback {
+    frame: usize,
+    reverse: bool,
+    accumulated: f32,
+    finished: bool,
+    // Strong handles of the streamed frames currently kept alive, keyed by frame number
+    streamed: HashMap<usize, Handle<Image>>,
+}
+
+// Result of advancing an animation by one frame
+struct FrameStep {
+    frame: usize,
+    reverse: bool,
+    cycle_completed: bool,
+    finished: bool,
+}
+
+// Advances `frame` by one in a `len` frames long animation according to `mode`
+fn step_frame(frame: usize, reverse: bool, len: usize, mode: PlaybackMode) -> FrameStep {
+    let last = len.saturating_sub(1);
+    match mode {
+        PlaybackMode::Loop => FrameStep {
+            frame: if frame >= last { 0 } else { frame + 1 },
+            reverse: false,
+            cycle_completed: frame >= last,
+            finished: false,
+        },
+        PlaybackMode::PingPong if last == 0 => FrameStep {
+            frame: 0,
+            reverse: false,
+            cycle_completed: true,
+            finished: false,
+        },
+        PlaybackMode::PingPong if reverse => FrameStep {
+            frame: frame.saturating_sub(1),
+            reverse: frame > 1,
+            cycle_completed: frame <= 1,
+            finished: false,
+        },
+        PlaybackMode::PingPong => FrameStep {
+            frame: (frame + 1).min(last),
+            reverse: frame + 1 >= last,
+            cycle_completed: false,
+            finished: false,
+        },
+        // Stepping past the last frame keeps it and finishes, so it is shown as long as the others
+        PlaybackMode::Once => FrameStep {
+            frame: (frame + 1).min(last),
+            reverse: false,
+            cycle_completed: false,
+            finished: frame >= last,
+        },
+    }
+}
+
+// Frames of a sequence to keep resident: the current one plus the next `preload` ones, and
+// always at least the next one so that playback can step onto it
+fn wanted_frames(
+    frame: usize,
+    reverse: bool,
+    len: usize,
+    mode: PlaybackMode,
+    preload: usize,
+) -> Vec<usize> {
+    let mut wanted = vec![frame];
+    let (mut frame, mut reverse) = (frame, reverse);
+    for _ in 0..preload.max(1) {
+        let step = step_frame(frame, reverse, len, mode);
+        (frame, reverse) = (step.frame, step.reverse);
+        if !wanted.contains(&frame) {
+            wanted.push(frame);
+        }
+    }
+    wanted
+}
+
+fn sequence_frame_path(path_pattern: &str, number: usize, digits: usize) -> String {
+    path_pattern.replace("{}", &format!("{:0digits$}", number))
+}
+
+pub struct AnimatedBackgroundPlugin;
+
+impl Plugin for AnimatedBackgroundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BackgroundAnimationFinished>()
+            .add_event::<BackgroundAnimationLooped>()
+            .add_systems(
+                Update,
+                (setup_animated_backgrounds, play_animated_backgrounds).chain(),
+            );
+    }
+}
+
+fn setup_animated_backgrounds(
+    mut commands: Commands,
+    layers: Res<LayerRegistry>,
+    animated_backgrounds: Query<Entity, Added<AnimatedBackground>>,
+) {
+    for entity in animated_backgrounds.iter() {
+        // The render layer is kept when already set, e.g. to the incoming background of a
+        // transition
+        commands
+            .entity(entity)
+            .insert(AnimationPlayback::default())
+            .insert_if_new(layers.render_layers(BACKGROUND_LAYER));
+    }
+}
+
+fn play_animated_backgrounds(
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    mut finished_events: EventWriter<BackgroundAnimationFinished>,
+    mut looped_events: EventWriter<BackgroundAnimationLooped>,
+    mut animated_backgrounds: Query<(
+        Entity,
+        &AnimatedBackground,
+        &mut AnimationPlayback,
+        &mut Sprite,
+    )>,
+) {
+    for (entity, animation, mut playback, mut sprite) in animated_backgrounds.iter_mut() {
+        let len = animation.frames.len();
+        if len == 0 {
+            continue;
+        }
+
+        if animation.playing && !playback.finished && animation.fps > 0.0 {
+            playback.accumulated += time.delta_secs() * animation.fps;
+        }
+
+        if let BackgroundFrames::Sequence {
+            path_pattern,
+            first,
+            digits,
+            preload,
+            ..
+        } = &animation.frames
+        {
+            let wanted = wanted_frames(
+                playback.frame,
+                playback.reverse,
+                len,
+                animation.mode,
+                *preload,
+            );
+            playback.streamed.retain(|frame, _| wanted.contains(frame));
+            for frame in wanted {
+                playback.streamed.entry(frame).or_insert_with(|| {
+                    asset_server.load(sequence_frame_path(path_pattern, first + frame, *digits))
+                });
+            }
+        }
+
+        while playback.accumulated >= 1.0 && !playback.finished {
+            let step = step_frame(playback.frame, playback.reverse, len, animation.mode);
+            // Hold the current frame until the next streamed one has finished loading,
+            // rather than flashing an empty sprite
+            let next_ready = match &animation.frames {
+                BackgroundFrames::Atlas { .. } => true,
+                BackgroundFrames::Sequence { .. } => playback
+                    .streamed
+                    .get(&step.frame)
+                    .is_some_and(|next| asset_server.is_loaded_with_dependencies(next)),
+            };
+            if !next_ready {
+                playback.accumulated = playback.accumulated.min(1.0);
+                break;
+            }
+            playback.accumulated -= 1.0;
+            playback.frame = step.frame;
+            playback.reverse = step.reverse;
+            if step.cycle_completed {
+                looped_events.send(BackgroundAnimationLooped { entity });
+            }
+            if step.finished {
+                playback.finished = true;
+                finished_events.send(BackgroundAnimationFinished { entity });
+            }
+        }
+
+        match &animation.frames {
+            BackgroundFrames::Atlas {
+                image,
+                layout,
+                first,
+                ..
+            } => {
+                let index = first + playback.frame;
+                let up_to_date = sprite.image == *image
+                    && sprite
+                        .texture_atlas
+                        .as_ref()
+                        .is_some_and(|atlas| atlas.layout == *layout && atlas.index == index);
+                if !up_to_date {
+                    sprite.image = image.clone();
+                    sprite.texture_atlas = Some(TextureAtlas {
+                        layout: layout.clone(),
+                        index,
+                    });
+                }
+            }
+            BackgroundFrames::Sequence { .. } => {
+                if let Some(handle) = playback.streamed.get(&playback.frame)
+                    && sprite.image != *handle
+                    && asset_server.is_loaded_with_dependencies(handle)
+                {
+                    sprite.image = handle.clone();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frames visited in `steps` steps from the first one, with the cycle and end flags
+    fn play(len: usize, mode: PlaybackMode, steps: usize) -> Vec<(usize, bool, bool)> {
+        let (mut frame, mut reverse) = (0, false);
+        (0..steps)
+            .map(|_| {
+                let step = step_frame(frame, reverse, len, mode);
+                (frame, reverse) = (step.frame, step.reverse);
+                (step.frame, step.cycle_completed, step.finished)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loop_wraps_to_the_first_frame() {
+        assert_eq!(
+            play(3, PlaybackMode::Loop, 4),
+            [
+                (1, false, false),
+                (2, false, false),
+                (0, true, false),
+                (1, false, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn ping_pong_reverses_at_both_ends() {
+        let frames: Vec<usize> = play(3, PlaybackMode::PingPong, 6)
+            .iter()
+            .map(|(frame, _, _)| *frame)
+            .collect();
+        assert_eq!(frames, [1, 2, 1, 0, 1, 2]);
+        // A cycle completes when it is back on the first frame
+        let cycles: Vec<bool> = play(3, PlaybackMode::PingPong, 4)
+            .iter()
+            .map(|(_, cycle_completed, _)| *cycle_completed)
+            .collect();
+        assert_eq!(cycles, [false, false, false, true]);
+    }
+
+    #[test]
+    fn once_stops_on_the_last_frame() {
+        // Finishes one step after reaching the last frame, once it has been shown
+        assert_eq!(
+            play(3, PlaybackMode::Once, 4),
+            [
+                (1, false, false),
+                (2, false, false),
+                (2, false, true),
+                (2, false, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn sequences_always_stream_the_next_frame() {
+        assert_eq!(wanted_frames(0, false, 5, PlaybackMode::Loop, 0), [0, 1]);
+        assert_eq!(wanted_frames(4, false, 5, PlaybackMode::Loop, 0), [4, 0]);
+        assert_eq!(wanted_frames(1, false, 5, PlaybackMode::Loop, 2), [1, 2, 3]);
+        assert_eq!(wanted_frames(4, false, 5, PlaybackMode::Once, 2), [4]);
+    }
+
+    #[test]
+    fn single_frame_animations_stay_put() {
+        for mode in [
+            PlaybackMode::Loop,
+            PlaybackMode::PingPong,
+            PlaybackMode::Once,
+        ] {
+            assert!(play(1, mode, 3).iter().all(|(frame, _, _)| *frame == 0));
+        }
+    }
+}
@@ -1,114 +1,208 @@
+use bevy::asset::embedded_asset;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
+use bevy::render::camera::{NormalizedRenderTarget, RenderTarget, ScalingMode};
 use bevy::render::extract_component::{ExtractComponent, ExtractComponentPlugin};
-use bevy::render::extract_resource::{ExtractResource, ExtractResourcePlugin};
 use bevy::render::render_resource::{
     Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
 };
-use bevy::render::view::RenderLayers;
-use bevy::window::WindowResized;
+use bevy::utils::HashSet;
+use bevy::window::{PrimaryWindow, WindowClosed, WindowResized, WindowScaleFactorChanged};
 
-use crate::cameras::camera_plugin::CameraLayers;
+use crate::cameras::background_lut::BackgroundLutPassSettings;
+use crate::cameras::camera_plugin::GameCamera;
+use crate::cameras::composite_pass::CompositeBackground;
+use crate::cameras::layer_registry::{BACKGROUND_LAYER, LayerRegistry, RegisterLayerExt};
 
 // Marker component for the background camera
 #[derive(Component)]
 pub struct BackgroundCamera;
 
+// The surface (window or offscreen output image) a background camera produces the background for
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+pub enum BackgroundSurface {
+    Window(Entity),
+    Image(Handle<Image>),
+}
+
+impl BackgroundSurface {
+    // Resolves the surface a camera renders to, `None` for manual texture views
+    pub fn from_target(target: &RenderTarget, primary_window: Option<Entity>) -> Option<Self> {
+        match target.normalize(primary_window)? {
+            NormalizedRenderTarget::Window(window_ref) => Some(Self::Window(window_ref.entity())),
+            NormalizedRenderTarget::Image(handle) => Some(Self::Image(handle)),
+            NormalizedRenderTarget::TextureView(_) => None,
+        }
+    }
+}
+
 // Component to hold the handle for the background LUT
 #[derive(Component, Clone, ExtractComponent, Default)] // Make sure ExtractComponent is derived
+#[require(BackgroundLutPassSettings)]
 pub struct BackgroundLutSource {
     pub lut_texture: Handle<Image>,
 }
 
-// Resource to hold the handle to the offscreen render target image
-#[derive(Resource, Clone, ExtractResource)]
+// Offscreen image the background camera renders into (one per surface).
+// Other layer cameras with their own grade (e.g. weather) reuse it together with
+// `BackgroundProcessedRenderTarget` and `BackgroundLutSource` to get the same LUT pass.
+#[derive(Component, Clone, ExtractComponent)]
 pub struct BackgroundRenderTarget {
     pub handle: Handle<Image>,
 }
-#[derive(Resource, Clone, ExtractResource)]
+
+// Output of the LUT pass for the background camera (one per surface)
+#[derive(Component, Clone, ExtractComponent)]
 pub struct BackgroundProcessedRenderTarget {
     pub handle: Handle<Image>,
 }
 
-// Default implementations to support init_resource
-impl Default for BackgroundRenderTarget {
-    fn default() -> Self {
-        Self {
-            handle: Handle::default(),
-        }
-    }
+// Processed background a camera composites its view over, the texture of the
+// `CompositeSource::Background` layers of its `CompositeStack`.
+// Kept in sync with the background camera of the same surface by `link_background_targets`.
+#[derive(Component, Clone)]
+pub struct BackgroundCompositeSource {
+    pub handle: Handle<Image>,
 }
 
-impl Default for BackgroundProcessedRenderTarget {
-    fn default() -> Self {
-        Self {
-            handle: Handle::default(),
-        }
-    }
-}
-const BACKGROUND_LUT_PATH: &str = "shaders/background_lut.png"; // <-- Your specific background LUT
+// Stretches a unit-sized quad (e.g. `Rectangle::new(1.0, 1.0)`) over the whole background
+// target of the main surface, see `BackgroundTargets`. The translation z is kept so the quad
+// can still be layered against other content.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct FillBackgroundTarget;
+
+// Default grade of the background, embedded in the crate like the shaders
+const BACKGROUND_LUT_PATH: &str =
+    "embedded://bevy_background_camera/cameras/shaders/background_lut.png";
 
 pub struct BackgroundCameraPlugin;
 
 impl Plugin for BackgroundCameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(ExtractComponentPlugin::<BackgroundLutSource>::default()) // Extract the LUT source
-            .add_plugins(ExtractResourcePlugin::<BackgroundRenderTarget>::default())
-            .add_plugins(ExtractResourcePlugin::<BackgroundProcessedRenderTarget>::default())
-            .init_resource::<BackgroundRenderTarget>()
-            .init_resource::<BackgroundProcessedRenderTarget>()
-            .add_systems(Startup, setup_background_camera)
-            .add_systems(Update, resize_background_render_target);
+        embedded_asset!(app, "shaders/background_lut.png");
+        app.register_layer(BACKGROUND_LAYER, 0, 0) // Rendered first
+            .add_plugins(ExtractComponentPlugin::<BackgroundLutSource>::default()) // Extract the LUT source
+            .add_plugins(ExtractComponentPlugin::<BackgroundRenderTarget>::default())
+            .add_plugins(ExtractComponentPlugin::<BackgroundProcessedRenderTarget>::default())
+            .add_systems(
+                Update,
+                (
+                    spawn_background_cameras,
+                    despawn_background_cameras,
+                    resize_background_render_target,
+                    link_background_targets,
+                    cover_main_background_area,
+                    fill_background_target,
+                )
+                    .chain(),
+            );
     }
 }
 
-fn setup_background_camera(
-    mut commands: Commands,
-    mut images: ResMut<Assets<Image>>,
-    asset_server: Res<AssetServer>,
-    windows: Query<&Window>,
-) {
-    info!("Setting up background camera");
-    let window = windows.single();
-    let size = Extent3d {
-        width: window.resolution.physical_width(),
-        height: window.resolution.physical_height(),
-        ..default()
-    };
-
-    // Create the image asset for the render target
-    let render_target_image = Image {
+// Builds an empty, transparent image usable both as a render attachment and as a texture
+pub fn create_background_target_image(label: &'static str, size: Extent3d) -> Image {
+    let mut image = Image {
         texture_descriptor: TextureDescriptor {
-            label: Some("background_render_target"),
+            label: Some(label),
             size,
             dimension: TextureDimension::D2,
             format: TextureFormat::bevy_default(), // Use the default format
             mip_level_count: 1,
             sample_count: 1,
             usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC // Copied over when the LUT pass is bypassed
                 | TextureUsages::COPY_DST
                 | TextureUsages::RENDER_ATTACHMENT, // Important!
             view_formats: &[],
         },
         ..default()
     };
-    // Fill with a transparent color initially
-    let mut render_target_image_clone = render_target_image.clone();
-    render_target_image_clone.resize(size);
+    image.resize(size);
     // Initialize with transparent pixels
-    render_target_image_clone.data.fill(0);
+    image.data.fill(0);
+    image
+}
 
-    let render_target_handle = images.add(render_target_image_clone.clone());
+// Looks up the background targets of the surfaces. Background content (fitted sprites, tiling
+// and sky quads, weather particles) is shared by every background camera, so it is sized for
+// the main surface: the one the game camera renders to, else the primary window. The layer
+// cameras of the other surfaces zoom to cover that area, see `cover_main_background_area`.
+#[derive(SystemParam)]
+pub struct BackgroundTargets<'w, 's> {
+    primary_window: Query<'w, 's, Entity, With<PrimaryWindow>>,
+    game_cameras: Query<'w, 's, &'static Camera, (With<GameCamera>, Without<BackgroundCamera>)>,
+    background_cameras: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static BackgroundSurface,
+            &'static BackgroundRenderTarget,
+        ),
+        With<BackgroundCamera>,
+    >,
+}
+
+impl BackgroundTargets<'_, '_> {
+    // Surface background content is sized for, `None` before any background camera exists
+    pub fn main_surface(&self) -> Option<BackgroundSurface> {
+        let primary_window = self.primary_window.get_single().ok();
+        self.game_cameras
+            .iter()
+            .filter_map(|camera| BackgroundSurface::from_target(&camera.target, primary_window))
+            .chain(primary_window.map(BackgroundSurface::Window))
+            .find(|surface| self.camera(surface).is_some())
+            .or_else(|| {
+                self.background_cameras
+                    .iter()
+                    .next()
+                    .map(|(_, surface, _)| surface.clone())
+            })
+    }
+
+    // Background camera of `surface`
+    pub fn camera(&self, surface: &BackgroundSurface) -> Option<Entity> {
+        self.background_cameras
+            .iter()
+            .find(|(_, background_surface, _)| *background_surface == surface)
+            .map(|(entity, _, _)| entity)
+    }
+
+    // Size of the background target of `surface`. Its background camera renders one world
+    // unit per target pixel.
+    pub fn size(&self, surface: &BackgroundSurface, images: &Assets<Image>) -> Option<UVec2> {
+        let (_, _, target) = self
+            .background_cameras
+            .iter()
+            .find(|(_, background_surface, _)| *background_surface == surface)?;
+        images.get(&target.handle).map(|image| image.size())
+    }
 
-    // Store the handle in a resource
-    commands.insert_resource(BackgroundRenderTarget {
-        handle: render_target_handle.clone(),
-    });
-    let mut processed_target_image = render_target_image_clone;
-    processed_target_image.texture_descriptor.label = Some("background_processed_render_target");
-    let processed_target_handle = images.add(processed_target_image);
-    commands.insert_resource(BackgroundProcessedRenderTarget {
-        handle: processed_target_handle.clone(),
-    });
+    // Size of the background target of the main surface, which is also the area of the
+    // background world every surface shows
+    pub fn main_size(&self, images: &Assets<Image>) -> Option<UVec2> {
+        self.size(&self.main_surface()?, images)
+    }
+}
+
+// Spawns a background camera with its own pair of render targets for `surface`
+fn spawn_background_camera(
+    commands: &mut Commands,
+    images: &mut Assets<Image>,
+    asset_server: &AssetServer,
+    layers: &LayerRegistry,
+    surface: BackgroundSurface,
+    size: Extent3d,
+) {
+    info!("Setting up background camera for {:?}", surface);
+    let render_target_handle = images.add(create_background_target_image(
+        "background_render_target",
+        size,
+    ));
+    let processed_target_handle = images.add(create_background_target_image(
+        "background_processed_render_target",
+        size,
+    ));
 
     // Debug log the size of render targets and handles
     info!(
@@ -128,55 +222,347 @@ fn setup_background_camera(
 
     // Spawn the background camera
     commands.spawn((
-        Camera2d::default(),
+        Camera2d,
         Camera {
-            order: CameraLayers::Background as isize, // Render first
-            target: bevy::render::camera::RenderTarget::Image(render_target_handle.clone()), // Render to our image!
+            order: layers.order(BACKGROUND_LAYER), // Render first
+            target: RenderTarget::Image(render_target_handle.clone()), // Render to our image!
             clear_color: ClearColorConfig::Custom(Color::srgba(0.0, 0.0, 0.0, 0.0)), // Set to transparent background
             ..default()
         },
-        RenderLayers::from_layers(&[CameraLayers::Background as usize]),
+        layers.render_layers(BACKGROUND_LAYER),
         BackgroundCamera, // Marker component
+        surface,
         BackgroundLutSource {
             lut_texture: background_lut_handle,
         },
-    ));
-    commands.spawn((
-        Sprite {
-            image: asset_server.load("forrest_wqhd.png"),
-            custom_size: Some(Vec2::new(2560.0, 1440.0)),
-            ..Default::default()
+        BackgroundRenderTarget {
+            handle: render_target_handle,
         },
-        Transform {
-            ..Default::default()
+        BackgroundProcessedRenderTarget {
+            handle: processed_target_handle,
         },
-        RenderLayers::layer((CameraLayers::Background as u8).into()),
     ));
 }
 
-// System to resize the render target when the window resize
+// Spawns a background camera for every surface a `CompositeBackground` camera renders to: its
+// window, the offscreen output when running headless or the low resolution target of
+// `PixelPerfect`. Surfaces nothing composites a background into (e.g. the window only showing
+// the upscaled `PixelPerfect` frame) get none, so no background is rendered for nothing.
+#[allow(clippy::too_many_arguments)]
+fn spawn_background_cameras(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    asset_server: Res<AssetServer>,
+    layers: Res<LayerRegistry>,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    windows: Query<&Window>,
+    background_cameras: Query<&BackgroundSurface, With<BackgroundCamera>>,
+    cameras: Query<&Camera, With<CompositeBackground>>,
+) {
+    let primary_window = primary_window.get_single().ok();
+    let mut spawned = Vec::new();
+    for camera in cameras.iter() {
+        let Some(surface) = BackgroundSurface::from_target(&camera.target, primary_window) else {
+            continue;
+        };
+        if spawned.contains(&surface) || background_cameras.iter().any(|s| *s == surface) {
+            continue;
+        }
+        let size = match &surface {
+            BackgroundSurface::Window(window_entity) => {
+                windows.get(*window_entity).ok().map(|window| Extent3d {
+                    width: window.resolution.physical_width(),
+                    height: window.resolution.physical_height(),
+                    ..default()
+                })
+            }
+            BackgroundSurface::Image(handle) => images
+                .get(handle)
+                .map(|image| image.texture_descriptor.size),
+        };
+        // A minimized window reports a zero size, the camera is spawned once it has one
+        let Some(size) = size.filter(|size| size.width > 0 && size.height > 0) else {
+            continue;
+        };
+        spawned.push(surface.clone());
+        spawn_background_camera(
+            &mut commands,
+            &mut images,
+            &asset_server,
+            &layers,
+            surface,
+            size,
+        );
+    }
+}
+
+// Removes the background camera, and any other layer camera rendering for the same surface
+// (with them the last strong handles to their targets), once its window is closed
+fn despawn_background_cameras(
+    mut commands: Commands,
+    mut closed_events: EventReader<WindowClosed>,
+    layer_cameras: Query<(Entity, &BackgroundSurface)>,
+) {
+    for event in closed_events.read() {
+        for (entity, surface) in layer_cameras.iter() {
+            if *surface == BackgroundSurface::Window(event.window) {
+                info!("Removing layer camera for window {:?}", event.window);
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+// Points every camera opted in with `CompositeBackground` at the processed background of the
+// surface it renders to, and unlinks cameras that opted out
+fn link_background_targets(
+    mut commands: Commands,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    background_cameras: Query<
+        (&BackgroundSurface, &BackgroundProcessedRenderTarget),
+        With<BackgroundCamera>,
+    >,
+    cameras: Query<
+        (Entity, &Camera, Option<&BackgroundCompositeSource>),
+        With<CompositeBackground>,
+    >,
+    opted_out: Query<
+        Entity,
+        (
+            With<BackgroundCompositeSource>,
+            Without<CompositeBackground>,
+        ),
+    >,
+) {
+    for entity in opted_out.iter() {
+        commands
+            .entity(entity)
+            .remove::<BackgroundCompositeSource>();
+    }
+
+    let primary_window = primary_window.get_single().ok();
+    for (entity, camera, current_source) in cameras.iter() {
+        let Some(camera_surface) = BackgroundSurface::from_target(&camera.target, primary_window)
+        else {
+            continue;
+        };
+
+        let processed = background_cameras
+            .iter()
+            .find(|(surface, _)| **surface == camera_surface)
+            .map(|(_, processed)| processed);
+
+        match (processed, current_source) {
+            (Some(processed), Some(current)) if current.handle == processed.handle => {}
+            (Some(processed), _) => {
+                commands.entity(entity).insert(BackgroundCompositeSource {
+                    handle: processed.handle.clone(),
+                });
+            }
+            (None, Some(_)) => {
+                commands
+                    .entity(entity)
+                    .remove::<BackgroundCompositeSource>();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+// System to resize the render targets when their surface changes its physical resolution.
+// This covers every layer camera with a `BackgroundSurface`, not only the background one.
+// `WindowResized` only carries logical pixels and a scale factor change alters the physical
+// size without changing the logical one, so both events just mark the window as dirty and the
+// size is read back from `Window::resolution`. Bursts of events for one window collapse into
+// a single reallocation per frame. Offscreen outputs follow the size of their image.
 fn resize_background_render_target(
     mut resize_events: EventReader<WindowResized>,
+    mut scale_factor_events: EventReader<WindowScaleFactorChanged>,
     mut images: ResMut<Assets<Image>>,
-    background_target: Res<BackgroundRenderTarget>,
-    background_processed_target: Res<BackgroundProcessedRenderTarget>,
+    windows: Query<&Window>,
+    layer_cameras: Query<(
+        &BackgroundSurface,
+        &BackgroundRenderTarget,
+        &BackgroundProcessedRenderTarget,
+    )>,
+) {
+    let mut dirty_windows = HashSet::new();
+    dirty_windows.extend(resize_events.read().map(|event| event.window));
+    dirty_windows.extend(scale_factor_events.read().map(|event| event.window));
+
+    for (surface, background_target, background_processed_target) in layer_cameras.iter() {
+        let physical_size = match surface {
+            BackgroundSurface::Window(window_entity) => {
+                if !dirty_windows.contains(window_entity) {
+                    continue;
+                }
+                let Ok(window) = windows.get(*window_entity) else {
+                    continue;
+                };
+                UVec2::new(
+                    window.resolution.physical_width(),
+                    window.resolution.physical_height(),
+                )
+            }
+            BackgroundSurface::Image(handle) => {
+                let Some(image) = images.get(handle) else {
+                    continue;
+                };
+                image.size()
+            }
+        };
+        // A minimized window reports a zero size, which is not a valid texture size
+        if physical_size.x == 0 || physical_size.y == 0 {
+            continue;
+        }
+        let size = Extent3d {
+            width: physical_size.x,
+            height: physical_size.y,
+            ..default()
+        };
+
+        for handle in [
+            &background_target.handle,
+            &background_processed_target.handle,
+        ] {
+            // Only touch the asset when the size really changed, `get_mut` alone triggers a re-upload
+            if images.get(handle).map(|image| image.size()) == Some(physical_size) {
+                continue;
+            }
+            if let Some(image) = images.get_mut(handle) {
+                info!(
+                    "Resizing background target to {}x{} for {:?}",
+                    size.width, size.height, surface
+                );
+                image.resize(size);
+            }
+        }
+    }
+}
+
+// Background content is sized for the main surface, the layer cameras of every other surface
+// (background, weather, incoming background) zoom so that area covers their own target, cropping
+// it where the aspect ratios differ
+fn cover_main_background_area(
+    images: Res<Assets<Image>>,
+    background_targets: BackgroundTargets,
+    mut layer_cameras: Query<(&BackgroundSurface, &mut OrthographicProjection), With<Camera>>,
+) {
+    let Some(main_surface) = background_targets.main_surface() else {
+        return;
+    };
+    let Some(main_size) = background_targets
+        .size(&main_surface, &images)
+        .map(|size| size.as_vec2())
+    else {
+        return;
+    };
+    for (surface, mut projection) in layer_cameras.iter_mut() {
+        let scaling_mode = if *surface == main_surface {
+            if matches!(projection.scaling_mode, ScalingMode::WindowSize) {
+                continue;
+            }
+            ScalingMode::WindowSize
+        } else {
+            if matches!(
+                projection.scaling_mode,
+                ScalingMode::AutoMax { max_width, max_height }
+                    if max_width == main_size.x && max_height == main_size.y
+            ) {
+                continue;
+            }
+            ScalingMode::AutoMax {
+                max_width: main_size.x,
+                max_height: main_size.y,
+            }
+        };
+        info!(
+            "Fitting the background area of {:?} to {:?}",
+            surface, scaling_mode
+        );
+        projection.scaling_mode = scaling_mode;
+    }
+}
+
+// Keeps `FillBackgroundTarget` quads centered on the background cameras and sized to the
+// target of the main surface
+fn fill_background_target(
+    images: Res<Assets<Image>>,
+    background_targets: BackgroundTargets,
+    mut quads: Query<&mut Transform, With<FillBackgroundTarget>>,
 ) {
-    for event in resize_events.read() {
-        if let Some(image) = images.get_mut(&background_target.handle) {
-            let size = Extent3d {
-                width: event.width as u32,   // Use event physical size
-                height: event.height as u32, // Use event physical size
-                ..default()
-            };
-            image.resize(size);
+    let Some(target_size) = background_targets.main_size(&images) else {
+        return;
+    };
+    let scale = target_size.as_vec2().extend(1.0);
+    for mut transform in quads.iter_mut() {
+        // Avoid flagging the transform as changed every frame
+        if transform.scale != scale
+            || transform.translation.x != 0.0
+            || transform.translation.y != 0.0
+        {
+            transform.translation.x = 0.0;
+            transform.translation.y = 0.0;
+            transform.scale = scale;
         }
-        if let Some(image) = images.get_mut(&background_processed_target.handle) {
-            let size = Extent3d {
-                width: event.width as u32,
-                height: event.height as u32,
-                ..default()
-            };
-            image.resize(size);
+    }
+}
+
+// Layer cameras marked with `M` (weather, incoming background, ...) go away with the
+// background camera of their surface (e.g. offscreen outputs that are dropped), window closes
+// are already handled with the background camera
+pub fn despawn_orphaned_layer_cameras<M: Component>(
+    mut commands: Commands,
+    background_cameras: Query<&BackgroundSurface, With<BackgroundCamera>>,
+    layer_cameras: Query<(Entity, &BackgroundSurface), With<M>>,
+) {
+    for (entity, surface) in layer_cameras.iter() {
+        if !background_cameras
+            .iter()
+            .any(|background| background == surface)
+        {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// Keeps the targets of the layer cameras marked with `M` the size of the background targets
+// of the same surface
+pub fn resize_layer_targets<M: Component>(
+    mut images: ResMut<Assets<Image>>,
+    background_cameras: Query<
+        (&BackgroundSurface, &BackgroundRenderTarget),
+        With<BackgroundCamera>,
+    >,
+    layer_cameras: Query<
+        (
+            &BackgroundSurface,
+            &BackgroundRenderTarget,
+            &BackgroundProcessedRenderTarget,
+        ),
+        With<M>,
+    >,
+) {
+    for (surface, layer_target, layer_processed_target) in layer_cameras.iter() {
+        let Some(size) = background_cameras
+            .iter()
+            .find(|(background_surface, _)| *background_surface == surface)
+            .and_then(|(_, background_target)| images.get(&background_target.handle))
+            .map(|image| image.texture_descriptor.size)
+        else {
+            continue;
+        };
+        for handle in [&layer_target.handle, &layer_processed_target.handle] {
+            if images
+                .get(handle)
+                .map(|image| image.texture_descriptor.size)
+                == Some(size)
+            {
+                continue;
+            }
+            if let Some(image) = images.get_mut(handle) {
+                image.resize(size);
+            }
         }
     }
 }
@@ -0,0 +1,142 @@
+use bevy::image::{ImageFilterMode, ImageSampler};
+use bevy::prelude::*;
+use bevy::sprite::SpriteImageMode;
+use bevy::utils::HashMap;
+
+use super::background_camera::{BackgroundCamera, BackgroundTargets};
+use super::blend_mode::premultiply;
+
+// How a background sprite is sized and placed relative to the background target of the main
+// surface (see `BackgroundTargets`), the other surfaces show the same area.
+// Sprites with this component get their `custom_size` and `image_mode` managed by
+// `apply_background_fit`. The fit is centered on the sprite's own translation.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub enum BackgroundFit {
+    // Scale uniformly until the target is fully covered, cropping the overflow
+    Cover,
+    // Scale uniformly until the image fits inside the target, the rest is cleared to `letterbox`
+    Contain { letterbox: Color },
+    // Scale each axis independently to the target size, ignoring the aspect ratio
+    Stretch,
+    // Repeat the image at its native size across the whole target
+    Tile,
+    // Largest integer scale that still fits, centered on whole pixels. The sampler is left
+    // alone since the image asset may be shared: load it with nearest filtering (e.g.
+    // `ImagePlugin::default_nearest()`), an image with a linear sampler logs a warning.
+    PixelExact,
+}
+
+pub struct BackgroundFitPlugin;
+
+impl Plugin for BackgroundFitPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate, apply_background_fit);
+    }
+}
+
+// Size, in background world units, the fitted sprite should take for `fit`
+fn fitted_size(fit: BackgroundFit, image_size: Vec2, target_size: Vec2) -> Vec2 {
+    let ratio = target_size / image_size;
+    match fit {
+        BackgroundFit::Cover => image_size * ratio.max_element(),
+        BackgroundFit::Contain { .. } => image_size * ratio.min_element(),
+        BackgroundFit::Stretch | BackgroundFit::Tile => target_size,
+        BackgroundFit::PixelExact => image_size * ratio.min_element().floor().max(1.0),
+    }
+}
+
+// Re-applies the fit when the background target changes size, when a fitted image finishes
+// loading (or is hot-reloaded) and when the fit itself changes
+fn apply_background_fit(
+    mut last_target_size: Local<Option<UVec2>>,
+    mut image_events: EventReader<AssetEvent<Image>>,
+    images: Res<Assets<Image>>,
+    background_targets: BackgroundTargets,
+    mut cameras: Query<&mut Camera, With<BackgroundCamera>>,
+    mut sprites: Query<(Entity, Ref<BackgroundFit>, &mut Sprite, &mut Transform)>,
+    mut applied_offsets: Local<HashMap<Entity, Vec2>>,
+) {
+    // Forget despawned sprites, entity ids get reused
+    applied_offsets.retain(|entity, _| sprites.contains(*entity));
+
+    let Some(main_surface) = background_targets.main_surface() else {
+        return;
+    };
+    let Some(target_size) = background_targets.size(&main_surface, &images) else {
+        return;
+    };
+    let target_resized = *last_target_size != Some(target_size);
+    *last_target_size = Some(target_size);
+
+    let loaded_images: Vec<AssetId<Image>> = image_events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    for (entity, fit, mut sprite, mut transform) in sprites.iter_mut() {
+        if !target_resized && !fit.is_changed() && !loaded_images.contains(&sprite.image.id()) {
+            continue;
+        }
+        let Some(image_size) = images.get(&sprite.image).map(|image| image.size()) else {
+            // Not loaded yet, the load event will bring us back here
+            continue;
+        };
+
+        let size = fitted_size(*fit, image_size.as_vec2(), target_size.as_vec2());
+        sprite.custom_size = Some(size);
+        sprite.image_mode = match *fit {
+            BackgroundFit::Tile => SpriteImageMode::Tiled {
+                tile_x: true,
+                tile_y: true,
+                stretch_value: 1.0,
+            },
+            _ => SpriteImageMode::Auto,
+        };
+
+        // For pixel-exact output shift by half a pixel when the leftover space is odd, so
+        // the sprite edges land on whole target pixels. The offset applied last time is taken
+        // back out, the rest of the translation is the user's.
+        let mut offset = Vec2::ZERO;
+        if *fit == BackgroundFit::PixelExact {
+            let leftover = target_size.as_ivec2() - size.as_ivec2();
+            offset = Vec2::new(
+                if leftover.x % 2 != 0 { 0.5 } else { 0.0 },
+                if leftover.y % 2 != 0 { 0.5 } else { 0.0 },
+            );
+            // `ImageSampler::Default` follows the `ImagePlugin` setting, which is not known here
+            let is_linear = images.get(&sprite.image).is_some_and(|image| {
+                matches!(&image.sampler, ImageSampler::Descriptor(descriptor)
+                    if matches!(descriptor.mag_filter, ImageFilterMode::Linear))
+            });
+            if is_linear {
+                warn!(
+                    "Pixel exact background image {:?} is not sampled with nearest filtering, \
+                     its pixels will blur",
+                    sprite.image.path()
+                );
+            }
+        }
+        let previous = applied_offsets.insert(entity, offset).unwrap_or_default();
+        if offset != previous {
+            let translation = transform.translation.truncate() - previous + offset;
+            transform.translation = translation.extend(transform.translation.z);
+        }
+
+        if let BackgroundFit::Contain { letterbox } = *fit
+            && let Some(mut camera) = background_targets
+                .camera(&main_surface)
+                .and_then(|entity| cameras.get_mut(entity).ok())
+        {
+            // Cleared straight into the layer target, which holds premultiplied colors
+            camera.clear_color = ClearColorConfig::Custom(premultiply(letterbox));
+        }
+
+        info!(
+            "Fitted background sprite ({:?}) {}x{} into {}x{}",
+            *fit, image_size.x, image_size.y, target_size.x, target_size.y
+        );
+    }
+}
@@ -4,29 +4,35 @@
 use bevy::{
     app::{App, Plugin},
     asset::DirectAssetAccessExt,
+    asset::RenderAssetUsages,
+    asset::embedded_asset,
+    asset::{Assets, Handle},
     core_pipeline::{
         core_2d::graph::Core2d, fullscreen_vertex_shader::fullscreen_shader_vertex_state,
     },
     ecs::{
+        component::Component,
         query::QueryItem,
         system::Resource,
         world::{FromWorld, World},
     },
-    image::BevyDefault,
+    image::{BevyDefault, Image},
     log::{info, warn},
+    math::Vec3,
     render::{
         RenderApp,
-        camera::{ExtractedCamera, NormalizedRenderTarget},
+        extract_component::{ExtractComponent, ExtractComponentPlugin},
         render_asset::RenderAssets,
         render_graph::{
             NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
         },
         render_resource::{
             AddressMode, BindGroupEntries, BindGroupLayout, BindGroupLayoutEntries,
-            CachedRenderPipelineId, ColorTargetState, ColorWrites, FilterMode, FragmentState,
-            MultisampleState, Operations, PipelineCache, PrimitiveState, RenderPassColorAttachment,
-            RenderPassDescriptor, RenderPipelineDescriptor, Sampler, SamplerBindingType,
-            SamplerDescriptor, ShaderStages, TextureFormat, TextureSampleType,
+            CachedRenderPipelineId, ColorTargetState, ColorWrites, Extent3d, FilterMode,
+            FragmentState, MultisampleState, Operations, PipelineCache, PrimitiveState,
+            RenderPassColorAttachment, RenderPassDescriptor, RenderPipelineDescriptor, Sampler,
+            SamplerBindingType, SamplerDescriptor, ShaderStages, TextureDimension, TextureFormat,
+            TextureSampleType,
             binding_types::{sampler, texture_2d},
         },
         renderer::{RenderContext, RenderDevice},
@@ -38,14 +44,96 @@ use super::background_camera::{
     BackgroundLutSource, BackgroundProcessedRenderTarget, BackgroundRenderTarget,
 };
 
-const SHADER_ASSET_PATH: &str = "shaders/night_shader.wgsl";
+const SHADER_ASSET_PATH: &str =
+    "embedded://bevy_background_camera/cameras/shaders/night_shader.wgsl";
+// Dimension of the LUT cube, must match LUT_DIM in night_shader.wgsl
+pub const LUT_DIM: u32 = 32;
+
+// Builds a LUT atlas (LUT_DIM slices of LUT_DIM x LUT_DIM laid out horizontally) that maps
+// every color to `grade` of it, both as 0..1 rgb
+fn graded_lut_image(grade: impl Fn(Vec3) -> Vec3) -> Image {
+    let max = (LUT_DIM - 1) as f32;
+    let mut data = Vec::with_capacity((LUT_DIM * LUT_DIM * LUT_DIM * 4) as usize);
+    for y in 0..LUT_DIM {
+        for z in 0..LUT_DIM {
+            for x in 0..LUT_DIM {
+                let color = grade(Vec3::new(x as f32, y as f32, z as f32) / max);
+                let [r, g, b] = (color.clamp(Vec3::ZERO, Vec3::ONE) * 255.0)
+                    .round()
+                    .to_array()
+                    .map(|channel| channel as u8);
+                data.extend_from_slice(&[r, g, b, 255]);
+            }
+        }
+    }
+    Image::new(
+        Extent3d {
+            width: LUT_DIM * LUT_DIM,
+            height: LUT_DIM,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        // Not sRGB, the stored values are read back exactly as written
+        TextureFormat::Rgba8Unorm,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+// LUT that maps every color to itself, for layers that go through the LUT pass but have no
+// grade yet
+pub fn identity_lut_image() -> Image {
+    graded_lut_image(|color| color)
+}
+
+// Shared `identity_lut_image`, for layers without a grade of their own
+#[derive(Resource, Clone)]
+pub struct IdentityLut {
+    pub handle: Handle<Image>,
+}
+
+impl FromWorld for IdentityLut {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            handle: world
+                .resource_mut::<Assets<Image>>()
+                .add(identity_lut_image()),
+        }
+    }
+}
+
+// LUT pulling colors toward their luminance, 0 keeps them and 1 leaves only gray
+pub fn desaturated_lut_image(amount: f32) -> Image {
+    graded_lut_image(|color| {
+        let luminance = color.dot(Vec3::new(0.2126, 0.7152, 0.0722));
+        color.lerp(Vec3::splat(luminance), amount)
+    })
+}
+
 // --- Background LUT Post Processing ---
 
+// Runtime switch of the LUT pass of a camera with a `BackgroundLutSource`, added along with it.
+// A disabled pass copies the render target to the processed target as is, so everything
+// downstream (composite, minimap displays) keeps working without respawning the camera.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, ExtractComponent)]
+pub struct BackgroundLutPassSettings {
+    pub enabled: bool,
+}
+
+impl Default for BackgroundLutPassSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
 pub struct BackgroundLutPlugin;
 
 impl Plugin for BackgroundLutPlugin {
     fn build(&self, app: &mut App) {
-        // BackgroundCameraPlugin already adds these plugins, don't add them again
+        embedded_asset!(app, "shaders/night_shader.wgsl");
+        // BackgroundCameraPlugin already adds the plugins extracting the LUT source and targets
+        app.add_plugins(ExtractComponentPlugin::<BackgroundLutPassSettings>::default())
+            .init_resource::<IdentityLut>();
 
         let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
             return;
@@ -77,66 +165,53 @@ pub struct BackgroundLutLabel;
 struct BackgroundLutNode;
 
 impl ViewNode for BackgroundLutNode {
-    // Query for background cameras specifically
+    // Query for the cameras graded into their own pair of render targets (background,
+    // incoming background, weather and minimap cameras), every other view is skipped by the
+    // runner.
     type ViewQuery = (
-        &'static ExtractedCamera,
         &'static BackgroundLutSource,
-        // &'static ViewTarget, // We get the target from ExtractedCamera
+        &'static BackgroundRenderTarget,
+        &'static BackgroundProcessedRenderTarget,
+        &'static BackgroundLutPassSettings,
     );
 
     fn run(
         &self,
         _graph: &mut RenderGraphContext,
         render_context: &mut RenderContext,
-        (extracted_camera, lut_source): QueryItem<Self::ViewQuery>,
+        (lut_source, source_target, destination_target, settings): QueryItem<Self::ViewQuery>,
         world: &World,
     ) -> Result<(), NodeRunError> {
         info!("Running BackgroundLutNode");
 
-        // Check if resource exists before trying to access it
-        if !world.contains_resource::<BackgroundRenderTarget>() {
-            warn!(
-                "BackgroundRenderTarget is missing in render world, skipping background LUT pass"
-            );
-            return Ok(());
-        }
-
-        if !world.contains_resource::<BackgroundProcessedRenderTarget>() {
-            warn!(
-                "BackgroundProcessedRenderTarget is missing in render world, skipping background LUT pass"
+        if !settings.enabled {
+            let gpu_images = world.resource::<RenderAssets<GpuImage>>();
+            let (Some(source_gpu_image), Some(destination_gpu_image)) = (
+                gpu_images.get(&source_target.handle),
+                gpu_images.get(&destination_target.handle),
+            ) else {
+                return Ok(());
+            };
+            // Both targets are created with the same size and format, copying skips the shader
+            if source_gpu_image.size != destination_gpu_image.size {
+                warn!("Background targets differ in size, skipping the LUT bypass copy");
+                return Ok(());
+            }
+            render_context.command_encoder().copy_texture_to_texture(
+                source_gpu_image.texture.as_image_copy(),
+                destination_gpu_image.texture.as_image_copy(),
+                Extent3d {
+                    width: source_gpu_image.size.x,
+                    height: source_gpu_image.size.y,
+                    depth_or_array_layers: 1,
+                },
             );
             return Ok(());
         }
 
-        // Now we can safely get the resource
-        let source_target = world.resource::<BackgroundRenderTarget>();
-
-        // Get the ID of the target image resource we expect the background camera to render to.
-        let expected_target_id = source_target.handle.id();
-
-        // Check if the camera currently being processed by this node
-        // is targeting an image AND if that image's handle ID matches our expected background target ID.
-        let is_correct_target = match &extracted_camera.target {
-            Some(NormalizedRenderTarget::Image(handle)) => handle.id() == expected_target_id,
-            _ => false, // Not rendering to an image or not the right one
-        };
-
-        if !is_correct_target {
-            // This node instance is not running for the correct background camera, skip.
-            return Ok(());
-        }
-        // --- End: Correct Check ---
-
-        // Now we know extracted_camera.target is Some(NormalizedRenderTarget::Image(handle))
-        // and the handle ID matches. We can safely proceed.
-
         let pipeline_cache = world.resource::<PipelineCache>();
         let background_lut_pipeline = world.resource::<BackgroundLutPipeline>();
 
-        // Get the handles for source and destination
-        let source_target = world.resource::<BackgroundRenderTarget>();
-        let destination_target = world.resource::<BackgroundProcessedRenderTarget>();
-
         let Some(pipeline) =
             pipeline_cache.get_render_pipeline(background_lut_pipeline.pipeline_id)
         else {
@@ -0,0 +1,452 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::view::RenderLayers;
+use bevy::window::PrimaryWindow;
+
+use super::background_camera::{
+    BackgroundCamera, BackgroundLutSource, BackgroundProcessedRenderTarget, BackgroundRenderTarget,
+    BackgroundSurface, create_background_target_image, despawn_orphaned_layer_cameras,
+    resize_layer_targets,
+};
+use super::composite_pass::CompositeBackground;
+use super::layer_registry::{
+    BACKGROUND_LAYER, INCOMING_BACKGROUND_LAYER, LayerRegistry, RegisterLayerExt,
+};
+
+// Noise used by `TransitionStyle::Dissolve` when no texture is given
+pub const TRANSITION_NOISE_IMAGE: Handle<Image> =
+    Handle::weak_from_u128(0x6f1d_2c8a_94b3_4e57_a1c0_5d7e_3b9f_8a26);
+const TRANSITION_NOISE_SIZE: u32 = 256;
+
+// How the incoming background replaces the outgoing one
+#[derive(Clone, Debug)]
+pub enum TransitionStyle {
+    Crossfade,
+    // The incoming background slides in along `direction` (screen space, y up), e.g.
+    // `Vec2::X` reveals it from the left edge to the right one. `softness` is the width of
+    // the blended edge as a fraction of the screen.
+    Wipe {
+        direction: Vec2,
+        softness: f32,
+    },
+    // Pixels switch over in the order of the red channel of `noise` (dark first), defaults
+    // to `TRANSITION_NOISE_IMAGE`. `softness` is the blended range of noise values.
+    Dissolve {
+        noise: Option<Handle<Image>>,
+        softness: f32,
+    },
+}
+
+impl TransitionStyle {
+    // Must match the TRANSITION_* constants in composite.wgsl, 0 meaning no transition
+    pub(crate) fn shader_id(&self) -> u32 {
+        match self {
+            TransitionStyle::Crossfade => 1,
+            TransitionStyle::Wipe { .. } => 2,
+            TransitionStyle::Dissolve { .. } => 3,
+        }
+    }
+
+    // Wipe direction with the y axis flipped to texture space, and the edge softness
+    pub(crate) fn shader_params(&self) -> Vec4 {
+        // smoothstep needs a non-empty range
+        const MIN_SOFTNESS: f32 = 1e-4;
+        match self {
+            TransitionStyle::Crossfade => Vec4::ZERO,
+            TransitionStyle::Wipe {
+                direction,
+                softness,
+            } => {
+                let direction = direction.normalize_or(Vec2::X);
+                Vec4::new(direction.x, -direction.y, softness.max(MIN_SOFTNESS), 0.0)
+            }
+            TransitionStyle::Dissolve { softness, .. } => {
+                Vec4::new(0.0, 0.0, softness.max(MIN_SOFTNESS), 0.0)
+            }
+        }
+    }
+
+    pub(crate) fn noise(&self) -> Handle<Image> {
+        match self {
+            TransitionStyle::Dissolve {
+                noise: Some(noise), ..
+            } => noise.clone(),
+            _ => TRANSITION_NOISE_IMAGE,
+        }
+    }
+}
+
+// Transitions the background composited by this camera to the content on the
+// `INCOMING_BACKGROUND_LAYER`. Spawn the new background on that layer, then
+// insert this component next to `CompositeBackground`.
+// The content on both layers is recorded when the transition starts, later spawned content
+// is left alone.
+// When done a `BackgroundTransitionFinished` is sent, the component removed and the incoming
+// content moved to the background layer (other layers it is on are kept). With
+// `despawn_outgoing` the entities that were on the background layer, alone or among other
+// layers, are despawned at that point.
+#[derive(Component, Clone, Debug)]
+pub struct BackgroundTransition {
+    pub style: TransitionStyle,
+    // In seconds
+    pub duration: f32,
+    pub easing: EaseFunction,
+    pub despawn_outgoing: bool,
+    elapsed: f32,
+    incoming: Vec<Entity>,
+    outgoing: Vec<Entity>,
+}
+
+impl BackgroundTransition {
+    pub fn new(style: TransitionStyle, duration: f32) -> Self {
+        Self {
+            style,
+            duration,
+            easing: EaseFunction::CubicInOut,
+            despawn_outgoing: true,
+            elapsed: 0.0,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        }
+    }
+
+    pub fn with_easing(mut self, easing: EaseFunction) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn keep_outgoing(mut self) -> Self {
+        self.despawn_outgoing = false;
+        self
+    }
+
+    // Eased progress, 0 shows only the outgoing background and 1 only the incoming one
+    pub fn progress(&self) -> f32 {
+        let t = if self.duration > 0.0 {
+            self.elapsed / self.duration
+        } else {
+            1.0
+        };
+        EasingCurve::new(0.0, 1.0, self.easing).sample_clamped(t)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+// Sent when the `BackgroundTransition` of `camera` completed
+#[derive(Event, Clone, Copy, Debug)]
+pub struct BackgroundTransitionFinished {
+    pub camera: Entity,
+}
+
+// Marker component for the camera rendering the incoming background of a surface.
+// It is only active while a transition is running.
+#[derive(Component)]
+pub struct IncomingBackgroundCamera;
+
+// Processed incoming background a camera transitions to.
+// Kept in sync with the incoming background camera of the same surface by
+// `link_incoming_background_targets`.
+#[derive(Component, Clone)]
+pub struct IncomingBackgroundCompositeSource {
+    pub handle: Handle<Image>,
+}
+
+pub struct BackgroundTransitionPlugin;
+
+impl Plugin for BackgroundTransitionPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_layer(INCOMING_BACKGROUND_LAYER, 10, 1)
+            .add_event::<BackgroundTransitionFinished>()
+            .add_systems(Startup, setup_transition_noise)
+            .add_systems(
+                Update,
+                (
+                    spawn_incoming_background_cameras,
+                    despawn_orphaned_layer_cameras::<IncomingBackgroundCamera>,
+                    resize_layer_targets::<IncomingBackgroundCamera>,
+                    sync_incoming_background_luts,
+                    link_incoming_background_targets,
+                    advance_background_transitions,
+                    activate_incoming_background_cameras,
+                )
+                    .chain(),
+            );
+    }
+}
+
+// Smooth value noise (two octaves of an interpolated random lattice) for dissolves
+fn transition_noise_image() -> Image {
+    let mut state: u32 = 0x9e37_79b9;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as f32 / u32::MAX as f32
+    };
+    let octave = |cells: usize, values: &[f32], x: f32, y: f32| {
+        let (fx, fy) = (x * cells as f32, y * cells as f32);
+        let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+        let (tx, ty) = (fx.fract(), fy.fract());
+        let (tx, ty) = (tx * tx * (3.0 - 2.0 * tx), ty * ty * (3.0 - 2.0 * ty));
+        // The lattice wraps so the noise tiles
+        let value = |x: usize, y: usize| values[(y % cells) * cells + x % cells];
+        let top = value(x0, y0).lerp(value(x0 + 1, y0), tx);
+        let bottom = value(x0, y0 + 1).lerp(value(x0 + 1, y0 + 1), tx);
+        top.lerp(bottom, ty)
+    };
+    let coarse: Vec<f32> = (0..16 * 16).map(|_| next()).collect();
+    let fine: Vec<f32> = (0..64 * 64).map(|_| next()).collect();
+
+    let size = TRANSITION_NOISE_SIZE as usize;
+    let mut data = Vec::with_capacity(size * size * 4);
+    for y in 0..size {
+        for x in 0..size {
+            let (u, v) = (x as f32 / size as f32, y as f32 / size as f32);
+            let noise = octave(16, &coarse, u, v) * 0.7 + octave(64, &fine, u, v) * 0.3;
+            let value = (noise * 255.0) as u8;
+            data.extend_from_slice(&[value, value, value, 255]);
+        }
+    }
+    Image::new(
+        Extent3d {
+            width: TRANSITION_NOISE_SIZE,
+            height: TRANSITION_NOISE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8Unorm,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+fn setup_transition_noise(mut images: ResMut<Assets<Image>>) {
+    images.insert(&TRANSITION_NOISE_IMAGE, transition_noise_image());
+}
+
+// Every background camera gets an (inactive) incoming background camera for the same
+// surface, graded with the same LUT
+fn spawn_incoming_background_cameras(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    layers: Res<LayerRegistry>,
+    background_cameras: Query<
+        (
+            &BackgroundSurface,
+            &BackgroundRenderTarget,
+            &BackgroundLutSource,
+        ),
+        Added<BackgroundCamera>,
+    >,
+) {
+    for (surface, background_target, lut_source) in background_cameras.iter() {
+        let Some(size) = images
+            .get(&background_target.handle)
+            .map(|image| image.texture_descriptor.size)
+        else {
+            continue;
+        };
+        info!("Setting up incoming background camera for {:?}", surface);
+        let render_target_handle = images.add(create_background_target_image(
+            "incoming_background_render_target",
+            size,
+        ));
+        let processed_target_handle = images.add(create_background_target_image(
+            "incoming_background_processed_render_target",
+            size,
+        ));
+        commands.spawn((
+            Camera2d,
+            Camera {
+                order: layers.order(INCOMING_BACKGROUND_LAYER),
+                target: RenderTarget::Image(render_target_handle.clone()),
+                clear_color: ClearColorConfig::Custom(Color::srgba(0.0, 0.0, 0.0, 0.0)),
+                is_active: false,
+                ..default()
+            },
+            layers.render_layers(INCOMING_BACKGROUND_LAYER),
+            IncomingBackgroundCamera,
+            surface.clone(),
+            lut_source.clone(),
+            BackgroundRenderTarget {
+                handle: render_target_handle,
+            },
+            BackgroundProcessedRenderTarget {
+                handle: processed_target_handle,
+            },
+        ));
+    }
+}
+
+type IncomingCameraFilter = (With<IncomingBackgroundCamera>, Without<BackgroundCamera>);
+
+// The incoming background is graded like the background it replaces
+fn sync_incoming_background_luts(
+    background_cameras: Query<(&BackgroundSurface, &BackgroundLutSource), With<BackgroundCamera>>,
+    mut incoming_cameras: Query<
+        (&BackgroundSurface, &mut BackgroundLutSource),
+        IncomingCameraFilter,
+    >,
+) {
+    for (surface, mut lut_source) in incoming_cameras.iter_mut() {
+        let Some((_, background_lut)) = background_cameras
+            .iter()
+            .find(|(background_surface, _)| *background_surface == surface)
+        else {
+            continue;
+        };
+        if lut_source.lut_texture != background_lut.lut_texture {
+            lut_source.lut_texture = background_lut.lut_texture.clone();
+        }
+    }
+}
+
+// Points every camera opted in with `CompositeBackground` at the incoming background of the
+// same surface, and unlinks cameras that opted out
+fn link_incoming_background_targets(
+    mut commands: Commands,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    incoming_cameras: Query<
+        (&BackgroundSurface, &BackgroundProcessedRenderTarget),
+        With<IncomingBackgroundCamera>,
+    >,
+    cameras: Query<
+        (Entity, &Camera, Option<&IncomingBackgroundCompositeSource>),
+        With<CompositeBackground>,
+    >,
+    opted_out: Query<
+        Entity,
+        (
+            With<IncomingBackgroundCompositeSource>,
+            Without<CompositeBackground>,
+        ),
+    >,
+) {
+    for entity in opted_out.iter() {
+        commands
+            .entity(entity)
+            .remove::<IncomingBackgroundCompositeSource>();
+    }
+
+    let primary_window = primary_window.get_single().ok();
+    for (entity, camera, current_source) in cameras.iter() {
+        let Some(camera_surface) = BackgroundSurface::from_target(&camera.target, primary_window)
+        else {
+            continue;
+        };
+        let processed = incoming_cameras
+            .iter()
+            .find(|(surface, _)| **surface == camera_surface)
+            .map(|(_, processed)| processed);
+
+        match (processed, current_source) {
+            (Some(processed), Some(current)) if current.handle == processed.handle => {}
+            (Some(processed), _) => {
+                commands
+                    .entity(entity)
+                    .insert(IncomingBackgroundCompositeSource {
+                        handle: processed.handle.clone(),
+                    });
+            }
+            (None, Some(_)) => {
+                commands
+                    .entity(entity)
+                    .remove::<IncomingBackgroundCompositeSource>();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+// Advances the running transitions. A finished one is removed, reported, and its incoming
+// content becomes the background.
+fn advance_background_transitions(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut finished_events: EventWriter<BackgroundTransitionFinished>,
+    layers: Res<LayerRegistry>,
+    mut transitions: Query<(Entity, &mut BackgroundTransition)>,
+    layered: Query<(Entity, &RenderLayers), Without<Camera>>,
+) {
+    let background = layers.render_layers(BACKGROUND_LAYER);
+    let incoming = layers.render_layers(INCOMING_BACKGROUND_LAYER);
+
+    for (camera, mut transition) in transitions.iter_mut() {
+        // Only the content present when the transition starts belongs to it, so content
+        // spawned for a later transition or elsewhere on these layers is never touched
+        if transition.is_added() {
+            let (mut incoming_content, mut outgoing_content) = (Vec::new(), Vec::new());
+            for (entity, entity_layers) in layered.iter() {
+                if entity_layers.intersects(&incoming) {
+                    incoming_content.push(entity);
+                } else if entity_layers.intersects(&background) {
+                    outgoing_content.push(entity);
+                }
+            }
+            transition.incoming = incoming_content;
+            transition.outgoing = outgoing_content;
+        }
+
+        transition.elapsed += time.delta_secs();
+        if !transition.is_finished() {
+            continue;
+        }
+
+        info!("Background transition of {:?} finished", camera);
+        commands.entity(camera).remove::<BackgroundTransition>();
+        // Content can be on more layers than these two (e.g. also shown on a minimap), only
+        // the incoming layer is swapped for the background one and the rest kept
+        for (entity, entity_layers) in layered.iter_many(&transition.incoming) {
+            let others = entity_layers.symmetric_difference(&entity_layers.intersection(&incoming));
+            commands.entity(entity).insert(others.union(&background));
+        }
+        if transition.despawn_outgoing {
+            for (entity, _) in layered.iter_many(&transition.outgoing) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        finished_events.send(BackgroundTransitionFinished { camera });
+    }
+}
+
+// The incoming backgrounds are only rendered while a transition needs them
+fn activate_incoming_background_cameras(
+    transitions: Query<(), With<BackgroundTransition>>,
+    mut incoming_cameras: Query<&mut Camera, With<IncomingBackgroundCamera>>,
+) {
+    let active = !transitions.is_empty();
+    for mut camera in incoming_cameras.iter_mut() {
+        if camera.is_active != active {
+            camera.is_active = active;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_is_eased_and_clamped() {
+        let mut transition = BackgroundTransition::new(TransitionStyle::Crossfade, 2.0)
+            .with_easing(EaseFunction::Linear);
+        assert_eq!(transition.progress(), 0.0);
+        transition.elapsed = 0.5;
+        assert!((transition.progress() - 0.25).abs() < 1e-6);
+        transition.elapsed = 3.0;
+        assert_eq!(transition.progress(), 1.0);
+        assert!(transition.is_finished());
+    }
+
+    #[test]
+    fn zero_duration_finishes_immediately() {
+        let transition = BackgroundTransition::new(TransitionStyle::Crossfade, 0.0);
+        assert_eq!(transition.progress(), 1.0);
+        assert!(transition.is_finished());
+    }
+}
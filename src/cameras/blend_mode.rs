@@ -0,0 +1,249 @@
+use bevy::math::{Vec3, Vec4};
+use bevy::prelude::*;
+
+// How a composited layer is combined with what is below it.
+// The discriminants are the values sent to composite.wgsl, keep them in sync with the
+// BLEND_* constants there. The functions below are the CPU reference of the shader code.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    // Plain "over", the layer replaces what is below according to its alpha
+    #[default]
+    Normal = 0,
+    // Darkens, white is neutral (shadow overlays)
+    Multiply = 1,
+    // Lightens, black is neutral
+    Screen = 2,
+    // Linear dodge, clamped to white (glow layers)
+    Add = 3,
+    // Multiply on dark backdrop areas, screen on light ones
+    Overlay = 4,
+    // Softer version of overlay, as specified by the W3C compositing spec
+    SoftLight = 5,
+}
+
+// How the colors of the composited layer textures are stored.
+// Everything bevy renders into our transparent-cleared targets with alpha blending ends up
+// premultiplied (a half transparent red sprite writes (0.5, 0, 0, 0.5)), so that is the
+// default. `Straight` is for sources filled by other means, e.g. images uploaded as is.
+// The composite always outputs premultiplied colors, which is what a transparent window
+// (`bevy::window::CompositeAlphaMode::PreMultiplied`) expects.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LayerAlphaMode {
+    Straight = 0,
+    #[default]
+    Premultiplied = 1,
+}
+
+// Converts a layer color to premultiplied alpha according to how it is stored
+pub fn to_premultiplied(alpha_mode: LayerAlphaMode, color: Vec4) -> Vec4 {
+    match alpha_mode {
+        LayerAlphaMode::Straight => (color.truncate() * color.w).extend(color.w),
+        LayerAlphaMode::Premultiplied => color,
+    }
+}
+
+// Straight color of a premultiplied one, black where fully transparent
+fn unpremultiply(color: Vec4) -> Vec3 {
+    if color.w > 0.0 {
+        color.truncate() / color.w
+    } else {
+        Vec3::ZERO
+    }
+}
+
+// Premultiplies a color, e.g. a clear color written directly into a layer target
+pub fn premultiply(color: Color) -> Color {
+    let linear = LinearRgba::from(color);
+    Color::LinearRgba(LinearRgba::new(
+        linear.red * linear.alpha,
+        linear.green * linear.alpha,
+        linear.blue * linear.alpha,
+        linear.alpha,
+    ))
+}
+
+fn soft_light_channel(backdrop: f32, source: f32) -> f32 {
+    if source <= 0.5 {
+        backdrop - (1.0 - 2.0 * source) * backdrop * (1.0 - backdrop)
+    } else {
+        let d = if backdrop <= 0.25 {
+            ((16.0 * backdrop - 12.0) * backdrop + 4.0) * backdrop
+        } else {
+            backdrop.sqrt()
+        };
+        backdrop + (2.0 * source - 1.0) * (d - backdrop)
+    }
+}
+
+fn overlay_channel(backdrop: f32, source: f32) -> f32 {
+    if backdrop <= 0.5 {
+        2.0 * backdrop * source
+    } else {
+        1.0 - 2.0 * (1.0 - backdrop) * (1.0 - source)
+    }
+}
+
+// Separable blend function B(backdrop, source) on straight (non-premultiplied) colors
+pub fn blend_rgb(mode: BlendMode, backdrop: Vec3, source: Vec3) -> Vec3 {
+    let per_channel = |f: fn(f32, f32) -> f32| {
+        Vec3::new(
+            f(backdrop.x, source.x),
+            f(backdrop.y, source.y),
+            f(backdrop.z, source.z),
+        )
+    };
+    match mode {
+        BlendMode::Normal => source,
+        BlendMode::Multiply => backdrop * source,
+        BlendMode::Screen => backdrop + source - backdrop * source,
+        BlendMode::Add => (backdrop + source).min(Vec3::ONE),
+        BlendMode::Overlay => per_channel(overlay_channel),
+        BlendMode::SoftLight => per_channel(soft_light_channel),
+    }
+}
+
+// Composites `source` over `backdrop` with `mode`, both and the result premultiplied.
+// W3C compositing: co = cs * (1 - ab) + cb * (1 - as) + as * ab * B(Cb, Cs), so where the
+// backdrop is transparent the source shows unblended.
+pub fn blend_layer(mode: BlendMode, backdrop: Vec4, source: Vec4) -> Vec4 {
+    let blended = blend_rgb(mode, unpremultiply(backdrop), unpremultiply(source));
+    let rgb = source.truncate() * (1.0 - backdrop.w)
+        + backdrop.truncate() * (1.0 - source.w)
+        + blended * source.w * backdrop.w;
+    rgb.extend(source.w + backdrop.w * (1.0 - source.w))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_close(actual: Vec4, expected: Vec4) {
+        assert!(
+            actual.abs_diff_eq(expected, EPSILON),
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    #[test]
+    fn normal_is_plain_over() {
+        let backdrop = Vec4::new(0.2, 0.4, 0.6, 1.0);
+        // Red at a quarter opacity, premultiplied
+        let source = Vec4::new(0.25, 0.0, 0.0, 0.25);
+        assert_close(
+            blend_layer(BlendMode::Normal, backdrop, source),
+            Vec4::new(0.4, 0.3, 0.45, 1.0),
+        );
+    }
+
+    #[test]
+    fn multiply_darkens() {
+        let backdrop = Vec4::new(0.5, 0.8, 1.0, 1.0);
+        let source = Vec4::new(0.5, 0.5, 0.0, 1.0);
+        assert_close(
+            blend_layer(BlendMode::Multiply, backdrop, source),
+            Vec4::new(0.25, 0.4, 0.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn screen_lightens() {
+        let backdrop = Vec4::new(0.5, 0.2, 0.0, 1.0);
+        let source = Vec4::new(0.5, 0.5, 1.0, 1.0);
+        assert_close(
+            blend_layer(BlendMode::Screen, backdrop, source),
+            Vec4::new(0.75, 0.6, 1.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn add_clamps_to_white() {
+        let backdrop = Vec4::new(0.5, 0.7, 0.1, 1.0);
+        let source = Vec4::new(0.25, 0.6, 0.1, 1.0);
+        assert_close(
+            blend_layer(BlendMode::Add, backdrop, source),
+            Vec4::new(0.75, 1.0, 0.2, 1.0),
+        );
+    }
+
+    #[test]
+    fn overlay_switches_on_backdrop() {
+        let backdrop = Vec4::new(0.25, 0.75, 0.5, 1.0);
+        let source = Vec4::new(0.5, 0.5, 0.2, 1.0);
+        // 2 * 0.25 * 0.5, 1 - 2 * 0.25 * 0.5, 2 * 0.5 * 0.2
+        assert_close(
+            blend_layer(BlendMode::Overlay, backdrop, source),
+            Vec4::new(0.25, 0.75, 0.2, 1.0),
+        );
+    }
+
+    #[test]
+    fn soft_light_follows_w3c_formula() {
+        let backdrop = Vec4::new(0.5, 0.2, 0.64, 1.0);
+        let source = Vec4::new(0.5, 0.25, 1.0, 1.0);
+        // 0.5 is neutral, 0.2 - 0.5 * 0.2 * 0.8, 0.64 + 1.0 * (0.8 - 0.64)
+        assert_close(
+            blend_layer(BlendMode::SoftLight, backdrop, source),
+            Vec4::new(0.5, 0.12, 0.8, 1.0),
+        );
+    }
+
+    #[test]
+    fn transparent_source_keeps_backdrop() {
+        let backdrop = Vec4::new(0.3, 0.6, 0.9, 1.0);
+        let source = Vec4::ZERO;
+        for mode in [
+            BlendMode::Normal,
+            BlendMode::Multiply,
+            BlendMode::Screen,
+            BlendMode::Add,
+            BlendMode::Overlay,
+            BlendMode::SoftLight,
+        ] {
+            assert_close(blend_layer(mode, backdrop, source), backdrop);
+        }
+    }
+
+    #[test]
+    fn transparent_backdrop_shows_source_unblended() {
+        let backdrop = Vec4::ZERO;
+        let source = Vec4::new(0.2, 0.4, 0.6, 1.0);
+        assert_close(blend_layer(BlendMode::Multiply, backdrop, source), source);
+    }
+
+    #[test]
+    fn premultiplied_edge_has_no_dark_fringe() {
+        // Edge of a red sprite drawn into a transparent target, composited over white
+        let backdrop = Vec4::ONE;
+        let source = Vec4::new(0.5, 0.0, 0.0, 0.5);
+        assert_close(
+            blend_layer(BlendMode::Normal, backdrop, source),
+            Vec4::new(1.0, 0.5, 0.5, 1.0),
+        );
+    }
+
+    #[test]
+    fn semi_transparent_layers_stay_premultiplied() {
+        let backdrop = Vec4::new(0.0, 0.0, 0.5, 0.5);
+        let source = Vec4::new(0.5, 0.0, 0.0, 0.5);
+        // Red over blue, both at half opacity: 0.75 alpha, twice as much red as blue
+        assert_close(
+            blend_layer(BlendMode::Normal, backdrop, source),
+            Vec4::new(0.5, 0.0, 0.25, 0.75),
+        );
+    }
+
+    #[test]
+    fn straight_layers_are_premultiplied() {
+        let straight = Vec4::new(1.0, 0.5, 0.0, 0.5);
+        assert_close(
+            to_premultiplied(LayerAlphaMode::Straight, straight),
+            Vec4::new(0.5, 0.25, 0.0, 0.5),
+        );
+        assert_close(
+            to_premultiplied(LayerAlphaMode::Premultiplied, straight),
+            straight,
+        );
+    }
+}
@@ -1,62 +1,784 @@
+use bevy::core_pipeline::bloom::{Bloom, BloomPrefilter};
+use bevy::core_pipeline::tonemapping::{DebandDither, Tonemapping};
+use bevy::input::mouse::{AccumulatedMouseScroll, MouseScrollUnit};
 use bevy::prelude::*;
-use bevy::render::camera::ScalingMode;
-use bevy::render::view::RenderLayers;
-use bevy::window::WindowResized;
+use bevy::render::camera::{
+    CameraOutputMode, CameraUpdateSystem, NormalizedRenderTarget, RenderTarget, ScalingMode,
+};
+use bevy::render::render_resource::{
+    BlendState, Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+};
+use bevy::ui::UiSystem;
+use bevy::utils::HashMap;
+use bevy::window::PrimaryWindow;
+
+use super::composite_pass::{
+    CompositeBackground, CompositeLayer, CompositeSource, CompositeStack, Letterbox, LetterboxFill,
+};
+use super::layer_registry::{GAME_LAYER, LayerRegistry, RegisterLayerExt, UI_LAYER, UPSCALE_LAYER};
 
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup)
-            .add_systems(Update, update_camera_zoom); // Keep zoom update // Keep zoom update
+        app.register_layer(GAME_LAYER, 30, 3)
+            .register_layer(UPSCALE_LAYER, 35, 5)
+            .register_layer(UI_LAYER, 40, 4)
+            .init_resource::<RenderOutput>()
+            .init_resource::<ViewportScaling>()
+            .init_resource::<SafeArea>()
+            .add_systems(Startup, setup)
+            .add_systems(Update, rts_camera_controller)
+            // Before bevy updates the projections, so a new policy or size shows the same frame
+            .add_systems(
+                PostUpdate,
+                (apply_viewport_scaling, update_upscale_cameras).before(CameraUpdateSystem),
+            )
+            .add_systems(
+                PostUpdate,
+                (update_safe_area, fit_safe_area_nodes)
+                    .chain()
+                    .after(apply_viewport_scaling)
+                    .after(update_upscale_cameras)
+                    .before(UiSystem::Layout),
+            );
+    }
+}
+
+// Marker component for the main game camera
+#[derive(Component)]
+pub struct GameCamera;
+
+// Marker component for the UI camera. It draws the `UI_LAYER` and Bevy UI on
+// top of the finished frame of the game camera (after compositing and tonemapping), so HUD
+// colors are never graded.
+#[derive(Component)]
+pub struct UiCamera;
+
+// Draws the low resolution game frame of `PixelPerfect` onto the output. The frame is an
+// upscaled layer of its `CompositeStack`, under what the camera renders of the `UPSCALE_LAYER`.
+#[derive(Component)]
+pub struct UpscaleCamera {
+    pub source: Handle<Image>,
+}
+
+// What an `RtsCameraController` keeps the view inside of
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraBounds {
+    // A fixed area in game world units
+    Rect(Rect),
+    // The area covered by a centered sprite, e.g. the map or a background image placed in
+    // the game world. Unbounded until its image is loaded.
+    Sprite(Entity),
+}
+
+// Strategy game style control of a camera: WASD/arrow keys and the window edges pan, dragging
+// with `drag_button` grabs the world and the wheel zooms toward the cursor. The view is kept
+// inside `bounds`, zooming out stops once the whole bounds are visible.
+// `zoom` is the projection scale on top of `ViewportScaling`, so resizing keeps the zoom.
+#[derive(Component, Clone, Debug)]
+pub struct RtsCameraController {
+    // Keyboard and edge scroll speed, in view heights per second
+    pub pan_speed: f32,
+    // Width in logical pixels of the window border that scrolls, 0 turns edge scrolling off
+    pub edge_margin: f32,
+    pub drag_button: Option<MouseButton>,
+    // 1 shows the `ViewportScaling` reference area, 2 twice as much
+    pub zoom: f32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    // Relative zoom change per wheel notch
+    pub zoom_step: f32,
+    pub bounds: Option<CameraBounds>,
+}
+
+impl Default for RtsCameraController {
+    fn default() -> Self {
+        Self {
+            pan_speed: 1.0,
+            edge_margin: 8.0,
+            drag_button: Some(MouseButton::Middle),
+            zoom: 1.0,
+            min_zoom: 0.5,
+            max_zoom: 2.0,
+            zoom_step: 0.1,
+            bounds: None,
+        }
+    }
+}
+
+// How much of the game world the game camera shows for a given window (or output image) size
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScalingPolicy {
+    // The reference width is always visible, the visible height follows the aspect ratio
+    FitWidth,
+    // The reference height is always visible, the visible width follows the aspect ratio
+    FitHeight,
+    // Exactly the reference area is visible, `letterbox` fills the bars around it
+    FitInside { letterbox: LetterboxFill },
+    // The window is filled and shows at most the reference area, the overflow is cropped
+    Fill,
+    // Always `world_units` high regardless of the reference resolution
+    FixedVertical { world_units: f32 },
+}
+
+// Scaling of the game camera, applied at startup and whenever it or the window size changes
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct ViewportScaling {
+    pub policy: ScalingPolicy,
+    // Resolution the game content is designed for, in world units
+    pub reference: Vec2,
+}
+
+impl Default for ViewportScaling {
+    fn default() -> Self {
+        Self {
+            policy: ScalingPolicy::FitHeight,
+            reference: Vec2::new(2560.0, 1440.0),
+        }
     }
 }
 
-fn setup(mut commands: Commands, asset_server: ResMut<AssetServer>) {
+impl ViewportScaling {
+    fn scaling_mode(&self) -> ScalingMode {
+        match self.policy {
+            ScalingPolicy::FitWidth => ScalingMode::FixedHorizontal {
+                viewport_width: self.reference.x,
+            },
+            ScalingPolicy::FitHeight => ScalingMode::FixedVertical {
+                viewport_height: self.reference.y,
+            },
+            ScalingPolicy::FitInside { .. } => ScalingMode::AutoMin {
+                min_width: self.reference.x,
+                min_height: self.reference.y,
+            },
+            ScalingPolicy::Fill => ScalingMode::AutoMax {
+                max_width: self.reference.x,
+                max_height: self.reference.y,
+            },
+            ScalingPolicy::FixedVertical { world_units } => ScalingMode::FixedVertical {
+                viewport_height: world_units,
+            },
+        }
+    }
+
+    // Part of a `target_size` view showing the reference area with `FitInside`, as fractions
+    // of the view with (0, 0) top left
+    fn letterbox(&self, target_size: Vec2) -> Option<Letterbox> {
+        let ScalingPolicy::FitInside { letterbox } = &self.policy else {
+            return None;
+        };
+        let scale = (target_size / self.reference).min_element();
+        let content = (self.reference * scale / target_size).min(Vec2::ONE);
+        Some(Letterbox {
+            rect: Rect::from_center_size(Vec2::splat(0.5), content),
+            fill: letterbox.clone(),
+        })
+    }
+}
+
+// Where the game camera presents the composited frame.
+// Insert before adding the plugins, e.g. `RenderOutput::Image { size: UVec2::new(1920, 1080) }`
+// to run without a window (CI, thumbnail rendering). The frame ends up in `OffscreenOutput`,
+// read it back with `Screenshot::image`, see the headless mode of examples/demo.rs.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RenderOutput {
+    #[default]
+    PrimaryWindow,
+    Image {
+        size: UVec2,
+    },
+}
+
+// Insert before adding the plugins to composite in HDR: the game camera gets an HDR target,
+// the background and game layers are combined in HDR and bloom, tonemapping and deband
+// dithering then apply to the combined frame.
+// The default bloom threshold of 1 keeps regular content from blooming, only HDR-bright
+// pixels do, e.g. layers with a `CompositeLayer::bloom` boost or colors brighter than white.
+#[derive(Resource, Clone)]
+pub struct HdrComposite {
+    pub bloom: Option<Bloom>,
+    pub tonemapping: Tonemapping,
+    pub deband_dither: DebandDither,
+}
+
+impl Default for HdrComposite {
+    fn default() -> Self {
+        Self {
+            bloom: Some(Bloom {
+                prefilter: BloomPrefilter {
+                    threshold: 1.0,
+                    threshold_softness: 0.25,
+                },
+                ..Bloom::NATURAL
+            }),
+            tonemapping: Tonemapping::TonyMcMapface,
+            deband_dither: DebandDither::Enabled,
+        }
+    }
+}
+
+// Insert before adding the plugins for pixel art: the game camera renders into an image of
+// `resolution`, so the background and weather of its surface do too, and the `UpscaleCamera`
+// scales that frame onto the output by the largest integer factor that fits, with nearest
+// filtering and `letterbox` filling the bars around it.
+// `ViewportScaling` applies to the low resolution, a reference equal to `resolution` gives one
+// world unit per game pixel.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct PixelPerfect {
+    pub resolution: UVec2,
+    pub letterbox: LetterboxFill,
+}
+
+impl Default for PixelPerfect {
+    fn default() -> Self {
+        Self {
+            resolution: UVec2::new(480, 270),
+            letterbox: LetterboxFill::default(),
+        }
+    }
+}
+
+impl PixelPerfect {
+    // Part of a view of `target_size` physical pixels the upscaled frame covers, as fractions
+    // of the view with (0, 0) top left. Bars are whole pixels so the game pixels stay square.
+    fn letterbox(&self, target_size: UVec2) -> Letterbox {
+        let factor = (target_size / self.resolution.max(UVec2::ONE))
+            .min_element()
+            .max(1);
+        let content = (self.resolution * factor).as_ivec2();
+        let offset = (target_size.as_ivec2() - content) / 2;
+        let target_size = target_size.as_vec2();
+        Letterbox {
+            rect: Rect::from_corners(
+                offset.as_vec2() / target_size,
+                (offset + content).as_vec2() / target_size,
+            ),
+            fill: self.letterbox.clone(),
+        }
+    }
+}
+
+// Safe gameplay area of the output in logical pixels, (0, 0) top left: the part of the window
+// (or offscreen output) between the letterbox bars, the whole output without bars. Lay out
+// UI with it, or add `FitSafeArea` to a node.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub struct SafeArea {
+    pub rect: Rect,
+}
+
+// Keeps an absolutely positioned UI node covering the `SafeArea`, a root for HUD elements
+// that must stay clear of the bars
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct FitSafeArea;
+
+// Offscreen image the game camera renders into with `RenderOutput::Image`.
+// It is created with `COPY_SRC` so the final frame can be read back.
+#[derive(Resource, Clone)]
+pub struct OffscreenOutput {
+    pub handle: Handle<Image>,
+}
+
+fn create_output_image(label: &'static str, size: UVec2) -> Image {
+    let size = Extent3d {
+        width: size.x,
+        height: size.y,
+        ..default()
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some(label),
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::bevy_default(),
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        ..default()
+    };
+    image.resize(size);
+    image
+}
+
+fn setup(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    render_output: Res<RenderOutput>,
+    hdr_composite: Option<Res<HdrComposite>>,
+    pixel_perfect: Option<Res<PixelPerfect>>,
+    layers: Res<LayerRegistry>,
+) {
+    let target = match *render_output {
+        RenderOutput::PrimaryWindow => RenderTarget::default(),
+        RenderOutput::Image { size } => {
+            info!("Rendering offscreen into a {}x{} image", size.x, size.y);
+            let handle = images.add(create_output_image("offscreen_output", size));
+            commands.insert_resource(OffscreenOutput {
+                handle: handle.clone(),
+            });
+            RenderTarget::Image(handle)
+        }
+    };
+    let game_target = match pixel_perfect {
+        Some(pixel_perfect) => {
+            let resolution = pixel_perfect.resolution;
+            info!(
+                "Rendering the game at {}x{} with integer upscaling",
+                resolution.x, resolution.y
+            );
+            let handle = images.add(create_output_image("pixel_perfect_target", resolution));
+            // Blends nothing but the game frame (and its own layer), the background is
+            // already part of that frame
+            commands.spawn((
+                Camera2d,
+                Camera {
+                    order: layers.order(UPSCALE_LAYER),
+                    target: target.clone(),
+                    clear_color: ClearColorConfig::Custom(Color::srgba(0.0, 0.0, 0.0, 0.0)),
+                    ..default()
+                },
+                layers.render_layers(UPSCALE_LAYER),
+                CompositeStack {
+                    layers: vec![
+                        CompositeLayer::new(CompositeSource::Image(handle.clone())).upscaled(),
+                        CompositeLayer::new(CompositeSource::View),
+                    ],
+                },
+                UpscaleCamera {
+                    source: handle.clone(),
+                },
+            ));
+            RenderTarget::Image(handle)
+        }
+        None => target.clone(),
+    };
+    let mut game_camera = commands.spawn((
+        Camera2d,
+        Camera {
+            order: layers.order(GAME_LAYER),
+            target: game_target,
+            clear_color: ClearColorConfig::Custom(Color::srgba(0.0, 0.0, 0.0, 0.0)),
+            hdr: hdr_composite.is_some(),
+            ..default()
+        },
+        layers.render_layers(GAME_LAYER),
+        GameCamera,
+        CompositeBackground, // Receives the background composite
+    ));
+    if let Some(hdr_composite) = hdr_composite {
+        info!("Compositing in HDR");
+        game_camera.insert((hdr_composite.tonemapping, hdr_composite.deband_dither));
+        if let Some(bloom) = hdr_composite.bloom.clone() {
+            game_camera.insert(bloom);
+        }
+    }
+    // Renders into its own transparent-cleared texture, which is blended over the finished
+    // frame of the game camera on output. That works whether the game camera is HDR or not.
+    // Without tonemapping and dithering the UI colors are written as they are.
     commands.spawn((
-        Camera2d::default(),
+        Camera2d,
         Camera {
-            order: CameraLayers::Game as isize,
+            order: layers.order(UI_LAYER),
+            target,
             clear_color: ClearColorConfig::Custom(Color::srgba(0.0, 0.0, 0.0, 0.0)),
             hdr: false,
+            output_mode: CameraOutputMode::Write {
+                blend_state: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                clear_color: ClearColorConfig::None,
+            },
             ..default()
         },
-        RenderLayers::from_layers(&[CameraLayers::Game as usize])
-            .without(CameraLayers::Background as usize),
+        Tonemapping::None,
+        DebandDither::Disabled,
+        layers.render_layers(UI_LAYER),
+        UiCamera,
+        IsDefaultUiCamera, // Bevy UI goes here rather than to the game camera
     ));
-    for num in 0..20 {
-        commands.spawn((
-            Sprite::from_image(asset_server.load("grid-outline.png")),
-            Transform::from_xyz(-1000. + 100. * num as f32, 0., 0.),
-            RenderLayers::layer(CameraLayers::Game as usize),
-        ));
+}
+
+// Logical and physical size of what a camera renders to, read from the window or image
+// directly since `Camera::logical_viewport_size` is only updated after this runs
+fn target_size(
+    camera: &Camera,
+    primary_window: Option<Entity>,
+    windows: &Query<&Window>,
+    images: &Assets<Image>,
+) -> Option<(Vec2, UVec2)> {
+    match camera.target.normalize(primary_window)? {
+        NormalizedRenderTarget::Window(window) => windows
+            .get(window.entity())
+            .ok()
+            .map(|window| (window.size(), window.physical_size())),
+        NormalizedRenderTarget::Image(handle) => images
+            .get(&handle)
+            .map(|image| (image.size().as_vec2(), image.size())),
+        NormalizedRenderTarget::TextureView(_) => None,
+    }
+}
+
+// Sets the projection of every game camera from `ViewportScaling`, and its letterbox for
+// `ScalingPolicy::FitInside`, when the camera appears and whenever the policy or its view
+// size changes. Layer cameras and the projection scale (it belongs to the
+// `RtsCameraController`) are left alone.
+fn apply_viewport_scaling(
+    mut commands: Commands,
+    scaling: Res<ViewportScaling>,
+    images: Res<Assets<Image>>,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    windows: Query<&Window>,
+    mut cameras: Query<
+        (
+            Entity,
+            &Camera,
+            &mut OrthographicProjection,
+            Option<&Letterbox>,
+        ),
+        With<GameCamera>,
+    >,
+    mut applied: Local<HashMap<Entity, (ViewportScaling, Vec2)>>,
+) {
+    // Forget despawned cameras, entity ids get reused
+    applied.retain(|entity, _| cameras.contains(*entity));
+
+    let primary_window = primary_window.get_single().ok();
+    for (entity, camera, mut projection, current_letterbox) in cameras.iter_mut() {
+        let Some((size, _)) = target_size(camera, primary_window, &windows, &images) else {
+            continue;
+        };
+        if size.min_element() <= 0.0
+            || applied.get(&entity).is_some_and(|(applied, applied_size)| {
+                *applied == *scaling && *applied_size == size
+            })
+        {
+            continue;
+        }
+        applied.insert(entity, (scaling.clone(), size));
+
+        info!(
+            "Applying {:?} with a {}x{} reference to a {}x{} view",
+            scaling.policy, scaling.reference.x, scaling.reference.y, size.x, size.y
+        );
+        projection.scaling_mode = scaling.scaling_mode();
+        match (scaling.letterbox(size), current_letterbox) {
+            (Some(letterbox), Some(current)) if letterbox == *current => {}
+            (Some(letterbox), _) => {
+                commands.entity(entity).insert(letterbox);
+            }
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<Letterbox>();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+// Keeps the low resolution frame of every `UpscaleCamera` at `PixelPerfect::resolution` and
+// its letterbox at the largest integer scale for the current output size
+fn update_upscale_cameras(
+    mut commands: Commands,
+    pixel_perfect: Option<Res<PixelPerfect>>,
+    mut images: ResMut<Assets<Image>>,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    windows: Query<&Window>,
+    cameras: Query<(Entity, &Camera, &UpscaleCamera, Option<&Letterbox>)>,
+) {
+    let Some(pixel_perfect) = pixel_perfect else {
+        return;
+    };
+    let primary_window = primary_window.get_single().ok();
+    for (entity, camera, upscale, current_letterbox) in cameras.iter() {
+        let resolution = pixel_perfect.resolution.max(UVec2::ONE);
+        if images.get(&upscale.source).map(|image| image.size()) != Some(resolution)
+            && let Some(image) = images.get_mut(&upscale.source)
+        {
+            info!(
+                "Resizing the pixel perfect target to {}x{}",
+                resolution.x, resolution.y
+            );
+            image.resize(Extent3d {
+                width: resolution.x,
+                height: resolution.y,
+                ..default()
+            });
+        }
+
+        let Some((_, size)) = target_size(camera, primary_window, &windows, &images) else {
+            continue;
+        };
+        // A minimized window reports a zero size
+        if size.min_element() == 0 {
+            continue;
+        }
+        let letterbox = pixel_perfect.letterbox(size);
+        if current_letterbox != Some(&letterbox) {
+            commands.entity(entity).insert(letterbox);
+        }
+    }
+}
+
+// World area covered by the sprite of `CameraBounds::Sprite`
+fn sprite_bounds(
+    entity: Entity,
+    sprites: &Query<(&Sprite, &GlobalTransform)>,
+    images: &Assets<Image>,
+) -> Option<Rect> {
+    let (sprite, transform) = sprites.get(entity).ok()?;
+    let size = sprite.custom_size.or_else(|| {
+        images
+            .get(&sprite.image)
+            .map(|image| image.size().as_vec2())
+    })?;
+    let (scale, _, translation) = transform.to_scale_rotation_translation();
+    Some(Rect::from_center_size(
+        translation.truncate(),
+        size * scale.truncate(),
+    ))
+}
+
+// Derives the `SafeArea` from the letterboxes on the way to the output: the one of the game
+// camera (`ScalingPolicy::FitInside`) inside the one of the `UpscaleCamera` (`PixelPerfect`)
+fn update_safe_area(
+    mut safe_area: ResMut<SafeArea>,
+    images: Res<Assets<Image>>,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    windows: Query<&Window>,
+    game_cameras: Query<(&Camera, Option<&Letterbox>), With<GameCamera>>,
+    upscale_cameras: Query<(&Camera, Option<&Letterbox>), With<UpscaleCamera>>,
+) {
+    let Ok((game_camera, game_letterbox)) = game_cameras.get_single() else {
+        return;
+    };
+    let full = Rect::new(0.0, 0.0, 1.0, 1.0);
+    let game_rect = game_letterbox.map_or(full, |letterbox| letterbox.rect);
+    let (output_camera, rect) = match upscale_cameras.get_single() {
+        Ok((camera, letterbox)) => {
+            let frame = letterbox.map_or(full, |letterbox| letterbox.rect);
+            (
+                camera,
+                Rect::from_corners(
+                    frame.min + game_rect.min * frame.size(),
+                    frame.min + game_rect.max * frame.size(),
+                ),
+            )
+        }
+        Err(_) => (game_camera, game_rect),
+    };
+    let primary_window = primary_window.get_single().ok();
+    let Some((size, _)) = target_size(output_camera, primary_window, &windows, &images) else {
+        return;
+    };
+    let rect = Rect::from_corners(rect.min * size, rect.max * size);
+    if safe_area.rect != rect {
+        safe_area.rect = rect;
     }
 }
 
-pub enum CameraLayers {
-    Background = 0,
-    Game = 1,
+fn fit_safe_area_nodes(safe_area: Res<SafeArea>, mut nodes: Query<(Ref<FitSafeArea>, &mut Node)>) {
+    for (fit, mut node) in nodes.iter_mut() {
+        if !safe_area.is_changed() && !fit.is_added() {
+            continue;
+        }
+        node.position_type = PositionType::Absolute;
+        node.left = Val::Px(safe_area.rect.min.x);
+        node.top = Val::Px(safe_area.rect.min.y);
+        node.width = Val::Px(safe_area.rect.width());
+        node.height = Val::Px(safe_area.rect.height());
+    }
+}
+
+// Largest zoom of a view showing `shown` at zoom 1 that still fits inside `bounds`
+fn max_zoom_in_bounds(max_zoom: f32, shown: Vec2, bounds: Option<Rect>) -> f32 {
+    bounds.map_or(max_zoom, |bounds| {
+        max_zoom.min((bounds.size() / shown).min_element())
+    })
 }
-fn update_camera_zoom(
-    mut resize_events: EventReader<WindowResized>,
-    mut query: Query<&mut OrthographicProjection>,
+
+// Moves a view of `size` centered on `position` inside `bounds`. Along an axis where the
+// bounds are smaller than the view (even fully zoomed in) it is centered on them.
+fn clamp_view_to_bounds(position: Vec2, size: Vec2, bounds: Rect) -> Vec2 {
+    let min = bounds.min + size / 2.0;
+    let max = bounds.max - size / 2.0;
+    Vec2::select(
+        min.cmple(max),
+        position.clamp(min.min(max), max.max(min)),
+        bounds.center(),
+    )
+}
+
+// Pans and zooms the cameras with an `RtsCameraController` from the primary window input.
+// Positions are mapped through the window, and through the letterbox of the `UpscaleCamera`
+// for the camera rendering its low resolution frame, so this works for offscreen game targets
+// too.
+#[allow(clippy::too_many_arguments)]
+fn rts_camera_controller(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    scroll: Res<AccumulatedMouseScroll>,
+    images: Res<Assets<Image>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    sprites: Query<(&Sprite, &GlobalTransform)>,
+    upscale_cameras: Query<(&UpscaleCamera, Option<&Letterbox>)>,
+    mut cameras: Query<(
+        &Camera,
+        &mut RtsCameraController,
+        &mut Transform,
+        &mut OrthographicProjection,
+        Option<&Letterbox>,
+    )>,
+    mut last_cursor: Local<Option<Vec2>>,
 ) {
-    // Reference resolution
-    const REFERENCE_WIDTH: f32 = 2560.0; // Width of the reference resolution
-    const REFERENCE_HEIGHT: f32 = 1440.0; // Height of the reference resolution
+    let window = windows.get_single().ok();
+    let cursor = window.and_then(|window| window.cursor_position());
+    let cursor_delta = cursor.zip(*last_cursor).map(|(cursor, last)| cursor - last);
+    *last_cursor = cursor;
 
-    for event in resize_events.read() {
-        for mut projection in query.iter_mut() {
-            // Calculate zoom factor based on the reference resolution
-            let zoom_factor_x = event.width / REFERENCE_WIDTH;
-            let zoom_factor_y = event.height / REFERENCE_HEIGHT;
+    let mut key_direction = Vec2::ZERO;
+    for (keys_pressed, direction) in [
+        ([KeyCode::KeyW, KeyCode::ArrowUp], Vec2::Y),
+        ([KeyCode::KeyS, KeyCode::ArrowDown], Vec2::NEG_Y),
+        ([KeyCode::KeyA, KeyCode::ArrowLeft], Vec2::NEG_X),
+        ([KeyCode::KeyD, KeyCode::ArrowRight], Vec2::X),
+    ] {
+        if keys.any_pressed(keys_pressed) {
+            key_direction += direction;
+        }
+    }
+    let wheel_notches = match scroll.unit {
+        MouseScrollUnit::Line => scroll.delta.y,
+        MouseScrollUnit::Pixel => scroll.delta.y / 100.0,
+    };
+
+    for (camera, mut controller, mut transform, mut projection, letterbox) in cameras.iter_mut() {
+        // View size at scale 1, known once bevy has updated the projection
+        let base_size = projection.area.size() / projection.scale;
+        if !base_size.is_finite() || base_size.min_element() <= 0.0 {
+            continue;
+        }
+        let old_zoom = controller.zoom;
+        let mut position = transform.translation.truncate();
+        // Part of the window the camera's frame covers, as fractions of the window
+        let frame = upscale_cameras
+            .iter()
+            .find(|(upscale, _)| {
+                matches!(&camera.target, RenderTarget::Image(handle) if *handle == upscale.source)
+            })
+            .and_then(|(_, letterbox)| letterbox)
+            .map_or(Rect::new(0.0, 0.0, 1.0, 1.0), |letterbox| letterbox.rect);
+        // Cursor relative to the view center as a fraction of the view, y up
+        let cursor_offset = window.zip(cursor).map(|(window, cursor)| {
+            ((cursor / window.size() - frame.min) / frame.size() - 0.5) * Vec2::new(1.0, -1.0)
+        });
 
-            // Use the average zoom factor or adjust based on your needs
-            let zoom_factor = (zoom_factor_x + zoom_factor_y) / 2.0;
+        // Wheel up zooms in, keeping the world point under the cursor in place
+        let mut zoom = controller.zoom;
+        if wheel_notches != 0.0 {
+            zoom *= (1.0 + controller.zoom_step).powf(-wheel_notches);
+        }
+
+        let dragging = controller
+            .drag_button
+            .is_some_and(|button| mouse_buttons.pressed(button));
+        let mut direction = key_direction;
+        if !dragging
+            && controller.edge_margin > 0.0
+            && let (Some(window), Some(cursor)) = (window, cursor)
+            && window.focused
+        {
+            let near_low = cursor.cmplt(Vec2::splat(controller.edge_margin));
+            let near_high = cursor.cmpge(window.size() - controller.edge_margin);
+            // Window y points down
+            direction += Vec2::select(near_high, Vec2::new(1.0, -1.0), Vec2::ZERO)
+                + Vec2::select(near_low, Vec2::new(-1.0, 1.0), Vec2::ZERO);
+        }
+        position += direction.normalize_or_zero()
+            * controller.pan_speed
+            * base_size.y
+            * old_zoom
+            * time.delta_secs();
+        if dragging && let (Some(window), Some(delta)) = (window, cursor_delta) {
+            position -= delta / (window.size() * frame.size())
+                * Vec2::new(1.0, -1.0)
+                * base_size
+                * old_zoom;
+        }
 
-            // Adjust the scaling mode to reflect the new zoom level
-            projection.scaling_mode = ScalingMode::WindowSize;
-            projection.scale = 1.0 / zoom_factor;
+        // Only the letterbox content is visible, the bounds apply to that part
+        let shown = base_size * letterbox.map_or(Vec2::ONE, |letterbox| letterbox.rect.size());
+        let bounds = match controller.bounds {
+            Some(CameraBounds::Rect(rect)) => Some(rect),
+            Some(CameraBounds::Sprite(entity)) => sprite_bounds(entity, &sprites, &images),
+            None => None,
+        };
+        let max_zoom = max_zoom_in_bounds(controller.max_zoom, shown, bounds);
+        zoom = zoom.clamp(controller.min_zoom, max_zoom.max(controller.min_zoom));
+        if zoom != old_zoom
+            && let Some(offset) = cursor_offset
+        {
+            position += offset * base_size * (old_zoom - zoom);
         }
+
+        if let Some(bounds) = bounds {
+            position = clamp_view_to_bounds(position, shown * zoom, bounds);
+        }
+
+        if zoom != controller.zoom {
+            controller.zoom = zoom;
+        }
+        if projection.scale != zoom {
+            projection.scale = zoom;
+        }
+        if position != transform.translation.truncate() {
+            transform.translation = position.extend(transform.translation.z);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_is_clamped_inside_bounds() {
+        let bounds = Rect::new(-100.0, -50.0, 100.0, 50.0);
+        let size = Vec2::new(40.0, 20.0);
+        assert_eq!(
+            clamp_view_to_bounds(Vec2::new(500.0, -500.0), size, bounds),
+            Vec2::new(80.0, -40.0)
+        );
+        assert_eq!(
+            clamp_view_to_bounds(Vec2::new(10.0, 5.0), size, bounds),
+            Vec2::new(10.0, 5.0)
+        );
+    }
+
+    #[test]
+    fn bounds_smaller_than_view_stays_centered() {
+        let bounds = Rect::new(10.0, 20.0, 30.0, 200.0);
+        // Too narrow: centered horizontally, still clamped vertically
+        assert_eq!(
+            clamp_view_to_bounds(Vec2::new(-300.0, 0.0), Vec2::new(40.0, 40.0), bounds),
+            Vec2::new(20.0, 40.0)
+        );
+        assert_eq!(
+            clamp_view_to_bounds(Vec2::new(300.0, 300.0), Vec2::splat(400.0), bounds),
+            bounds.center()
+        );
+    }
+
+    #[test]
+    fn max_zoom_limited_by_bounds() {
+        let shown = Vec2::new(320.0, 180.0);
+        // The height runs out first: 360 / 180
+        let bounds = Rect::new(0.0, 0.0, 1280.0, 360.0);
+        assert_eq!(max_zoom_in_bounds(4.0, shown, Some(bounds)), 2.0);
+        // The controller limit still applies to larger bounds
+        assert_eq!(max_zoom_in_bounds(1.5, shown, Some(bounds)), 1.5);
+        assert_eq!(max_zoom_in_bounds(4.0, shown, None), 4.0);
     }
 }
@@ -0,0 +1,229 @@
+use bevy::prelude::*;
+use bevy::render::camera::CameraUpdateSystem;
+use bevy::render::extract_component::{ExtractComponent, ExtractComponentPlugin};
+use bevy::render::view::ExtractedView;
+use bevy::render::{Render, RenderApp, RenderSet};
+use bevy::utils::HashMap;
+use bevy::window::PrimaryWindow;
+
+use super::background_camera::BackgroundSurface;
+use super::layer_registry::{
+    BACKGROUND_LAYER, GAME_LAYER, INCOMING_BACKGROUND_LAYER, LayerName, LayerRegistry,
+    WEATHER_LAYER,
+};
+
+// Trauma based screen shake. Add trauma on impacts, it decays over time and the shake grows
+// with its square, so small hits barely move the view and big ones rattle it.
+// The camera and the layer cameras of its surface (background, weather) shake by their
+// `layer_response`. Only what is rendered moves: `Transform` and `GlobalTransform` keep the
+// logical position, so gameplay and picking are unaffected.
+#[derive(Component, Clone, Debug)]
+pub struct CameraShake {
+    // 0 (still) to 1 (strongest), see `add_trauma`
+    pub trauma: f32,
+    // Trauma lost per second
+    pub decay: f32,
+    // Largest translation at full trauma, as a fraction of the view height
+    pub max_offset: f32,
+    // Largest rotation at full trauma, in radians
+    pub max_rotation: f32,
+    // How fast the shake changes direction, in noise samples per second
+    pub frequency: f32,
+    // Shake factor of each layer, layers not listed do not shake (e.g. the UI)
+    pub layer_response: Vec<(LayerName, f32)>,
+    time: f32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self {
+            trauma: 0.0,
+            decay: 0.8,
+            max_offset: 0.03,
+            max_rotation: 0.05,
+            frequency: 15.0,
+            layer_response: vec![
+                (GAME_LAYER, 1.0),
+                (BACKGROUND_LAYER, 0.3),
+                (INCOMING_BACKGROUND_LAYER, 0.3),
+                (WEATHER_LAYER, 0.3),
+            ],
+            time: 0.0,
+        }
+    }
+}
+
+impl CameraShake {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    // Sets (or adds) the shake factor of `layer`, 0 keeps it still
+    pub fn with_layer_response(mut self, layer: LayerName, factor: f32) -> Self {
+        match self
+            .layer_response
+            .iter_mut()
+            .find(|(existing, _)| *existing == layer)
+        {
+            Some((_, existing)) => *existing = factor,
+            None => self.layer_response.push((layer, factor)),
+        }
+        self
+    }
+
+    fn response(&self, layer: LayerName) -> f32 {
+        self.layer_response
+            .iter()
+            .find(|(existing, _)| *existing == layer)
+            .map_or(0.0, |(_, factor)| *factor)
+    }
+}
+
+// Shake of a camera this frame, in its own world units and relative to its view. Maintained by
+// `update_camera_shake` and applied to the extracted view only. Zero once the shake ends.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, ExtractComponent)]
+pub struct ShakeOffset {
+    pub translation: Vec2,
+    pub rotation: f32,
+}
+
+pub struct CameraShakePlugin;
+
+impl Plugin for CameraShakePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ExtractComponentPlugin::<ShakeOffset>::default())
+            // After the projections are updated, the offset scales with the view size
+            .add_systems(PostUpdate, update_camera_shake.after(CameraUpdateSystem));
+
+        let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        // The views are re-extracted from the unshaken transforms every frame, this runs
+        // before anything reads them for sorting or the view uniforms
+        render_app.add_systems(Render, apply_shake_offsets.in_set(RenderSet::ManageViews));
+    }
+}
+
+// Smooth 1D value noise in -1..1, a different curve for every `seed`
+fn shake_noise(t: f32, seed: u32) -> f32 {
+    let hash = |i: i32| {
+        let mut x = (i as u32).wrapping_mul(0x9E37_79B9) ^ seed.wrapping_mul(0x85EB_CA6B);
+        x ^= x >> 15;
+        x = x.wrapping_mul(0x2C1B_3C6D);
+        x ^= x >> 12;
+        x as f32 / u32::MAX as f32 * 2.0 - 1.0
+    };
+    let cell = t.floor();
+    let f = t - cell;
+    let f = f * f * (3.0 - 2.0 * f);
+    let cell = cell as i32;
+    hash(cell) + (hash(cell + 1) - hash(cell)) * f
+}
+
+type ShakenCamera = (
+    Entity,
+    &'static Camera,
+    Option<&'static OrthographicProjection>,
+    Option<&'static BackgroundSurface>,
+    Option<&'static ShakeOffset>,
+);
+
+// Advances every `CameraShake` and hands the resulting offset to the shaking camera and the
+// layer cameras of its surface, scaled by their layer response
+fn update_camera_shake(
+    mut commands: Commands,
+    time: Res<Time>,
+    layers: Res<LayerRegistry>,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    mut shakes: Query<(Entity, &Camera, &mut CameraShake)>,
+    cameras: Query<ShakenCamera>,
+) {
+    let primary_window = primary_window.get_single().ok();
+    // Which layer a camera renders is told by its order
+    let layer_of = |order: isize| {
+        layers
+            .iter()
+            .find(|(_, info)| info.order == order)
+            .map(|(name, _)| name)
+    };
+
+    let mut offsets = HashMap::new();
+    for (shake_entity, shake_camera, mut shake) in shakes.iter_mut() {
+        shake.trauma = (shake.trauma - shake.decay * time.delta_secs()).max(0.0);
+        if shake.trauma <= 0.0 {
+            continue;
+        }
+        shake.time += time.delta_secs();
+        let amount = shake.trauma * shake.trauma;
+        let t = shake.time * shake.frequency;
+        let direction = Vec2::new(shake_noise(t, 1), shake_noise(t, 2));
+        let rotation = shake_noise(t, 3) * shake.max_rotation * amount;
+
+        let surface = BackgroundSurface::from_target(&shake_camera.target, primary_window);
+        for (entity, camera, projection, camera_surface, _) in cameras.iter() {
+            let belongs = entity == shake_entity
+                || (camera_surface.is_some() && camera_surface == surface.as_ref());
+            let Some(layer) = layer_of(camera.order).filter(|_| belongs) else {
+                continue;
+            };
+            let factor = shake.response(layer);
+            if factor == 0.0 {
+                continue;
+            }
+            let view_height = projection.map_or(0.0, |projection| projection.area.height());
+            offsets.insert(
+                entity,
+                ShakeOffset {
+                    translation: direction * shake.max_offset * amount * factor * view_height,
+                    rotation: rotation * factor,
+                },
+            );
+        }
+    }
+
+    for (entity, _, _, _, current) in cameras.iter() {
+        let offset = offsets.remove(&entity).unwrap_or_default();
+        // Kept at zero rather than removed, removals do not reach the render world
+        if current.is_some_and(|current| *current == offset)
+            || (current.is_none() && offset == ShakeOffset::default())
+        {
+            continue;
+        }
+        commands.entity(entity).insert(offset);
+    }
+}
+
+// Moves the extracted views by their shake, the main world transforms stay untouched
+fn apply_shake_offsets(mut views: Query<(&mut ExtractedView, &ShakeOffset)>) {
+    for (mut view, offset) in views.iter_mut() {
+        if *offset == ShakeOffset::default() {
+            continue;
+        }
+        let shake = Transform::from_translation(offset.translation.extend(0.0))
+            .with_rotation(Quat::from_rotation_z(offset.rotation));
+        view.world_from_view = view.world_from_view * GlobalTransform::from(shake);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_is_bounded_and_continuous() {
+        for step in 0..1000 {
+            let t = step as f32 * 0.037;
+            let value = shake_noise(t, 7);
+            assert!((-1.0..=1.0).contains(&value));
+            assert!((value - shake_noise(t + 0.001, 7)).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn unlisted_layers_do_not_shake() {
+        let shake = CameraShake::default().with_layer_response(BACKGROUND_LAYER, 0.0);
+        assert_eq!(shake.response(GAME_LAYER), 1.0);
+        assert_eq!(shake.response(BACKGROUND_LAYER), 0.0);
+        assert_eq!(shake.response(LayerName("ui")), 0.0);
+    }
+}
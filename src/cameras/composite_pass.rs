@@ -1,13 +1,18 @@
 use bevy::{
+    asset::embedded_asset,
     core_pipeline::{
         core_2d::graph::{Core2d, Node2d},
         fullscreen_vertex_shader::fullscreen_shader_vertex_state,
     },
     ecs::query::QueryItem,
-    log::{error, info},
+    log::debug,
     prelude::*,
     render::{
         RenderApp,
+        extract_component::{
+            ComponentUniforms, DynamicUniformIndex, ExtractComponent, ExtractComponentPlugin,
+            UniformComponentPlugin,
+        },
         render_asset::RenderAssets,
         render_graph::{
             NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
@@ -17,24 +22,385 @@ use bevy::{
             ColorTargetState, ColorWrites, FragmentState, MultisampleState, Operations,
             PipelineCache, PrimitiveState, RenderPassColorAttachment, RenderPassDescriptor,
             RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages,
-            TextureFormat, TextureSampleType,
-            binding_types::{sampler, texture_2d},
+            ShaderType, TextureFormat, TextureSampleType,
+            binding_types::{sampler, texture_2d, uniform_buffer},
         },
         renderer::{RenderContext, RenderDevice},
-        texture::GpuImage,
+        texture::{FallbackImageZero, GpuImage},
         view::ViewTarget,
     },
 };
 
-use super::background_camera::BackgroundProcessedRenderTarget;
+use super::background_camera::BackgroundCompositeSource;
+use super::background_transition::{BackgroundTransition, IncomingBackgroundCompositeSource};
+use super::blend_mode::{BlendMode, LayerAlphaMode, premultiply};
+use super::weather::WeatherCompositeSource;
+
+const COMPOSITE_SHADER_PATH: &str =
+    "embedded://bevy_background_camera/cameras/shaders/composite.wgsl";
+
+// Number of layers `CompositeNode` blends in its single pass, must match
+// MAX_COMPOSITE_LAYERS in composite.wgsl. Enabled layers beyond it are ignored.
+pub const MAX_COMPOSITE_LAYERS: usize = 8;
+
+// Marks the cameras that get the processed background (and weather) composited under their
+// view. On an HDR camera (see `HdrComposite`) the layers are combined in HDR, before bloom and
+// tonemapping. Every other view, including the layer cameras themselves and UI or minimap cameras,
+// is left untouched by `CompositeNode`.
+// The layers themselves are listed in the `CompositeStack` of the camera, which defaults to
+// background, weather and the view of the camera on top.
+#[derive(Component, Clone, Copy, Debug, Default)]
+#[require(CompositeStack)]
+pub struct CompositeBackground;
+
+// Runtime switch of the composite pass of a camera, added along with its `CompositeStack`.
+// A disabled pass leaves the view as the camera rendered it: no background, weather or image
+// layers and no letterbox, e.g. the game layer alone for debugging or a photo mode.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, ExtractComponent)]
+pub struct CompositePassSettings {
+    pub enabled: bool,
+}
+
+impl Default for CompositePassSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+// Where the texture of a composited layer comes from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CompositeSource {
+    // What the camera itself rendered
+    View,
+    // The processed background of the surface the camera renders to
+    Background,
+    // The graded weather layer of that surface
+    Weather,
+    // Any image, e.g. an overlay rendered by another camera or loaded from disk.
+    // It is sampled over the whole view, so it should have the size of the view target,
+    // unless the layer is `upscaled`.
+    Image(Handle<Image>),
+}
+
+// One entry of a `CompositeStack`
+#[derive(Clone, Debug)]
+pub struct CompositeLayer {
+    pub source: CompositeSource,
+    // Scales the layer alpha, 0 hides the layer and 1 keeps it as rendered
+    pub opacity: f32,
+    pub blend_mode: BlendMode,
+    // How the colors of the source are stored, see `LayerAlphaMode`
+    pub alpha_mode: LayerAlphaMode,
+    // Extra brightness pushing the layer above the bloom threshold on HDR views, e.g. 1
+    // doubles it. 0 leaves the layer as rendered, ignored on LDR views.
+    pub bloom: f32,
+    // Stretches the texture over the `Letterbox` rect (the whole view without one) with
+    // nearest filtering, for low resolution layers like the game frame of `PixelPerfect`
+    pub upscale: bool,
+    pub enabled: bool,
+}
+
+impl CompositeLayer {
+    // Normal, fully opaque layer of a texture rendered by bevy (premultiplied)
+    pub fn new(source: CompositeSource) -> Self {
+        Self {
+            source,
+            opacity: 1.0,
+            blend_mode: BlendMode::Normal,
+            alpha_mode: LayerAlphaMode::Premultiplied,
+            bloom: 0.0,
+            upscale: false,
+            enabled: true,
+        }
+    }
+
+    // Layer of an image loaded from disk, which holds straight alpha
+    pub fn image(image: Handle<Image>) -> Self {
+        Self {
+            alpha_mode: LayerAlphaMode::Straight,
+            ..Self::new(CompositeSource::Image(image))
+        }
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        self
+    }
+
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    pub fn with_bloom(mut self, bloom: f32) -> Self {
+        self.bloom = bloom;
+        self
+    }
+
+    pub fn upscaled(mut self) -> Self {
+        self.upscale = true;
+        self
+    }
+}
+
+// Layers composited into the view of a camera, from bottom to top, in one pass of
+// `CompositeNode`. Add overlays by inserting layers, no new render graph node needed.
+// Only the first `MAX_COMPOSITE_LAYERS` enabled layers are used.
+#[derive(Component, Clone, Debug)]
+#[require(CompositePassSettings)]
+pub struct CompositeStack {
+    pub layers: Vec<CompositeLayer>,
+}
+
+impl Default for CompositeStack {
+    fn default() -> Self {
+        Self {
+            layers: vec![
+                CompositeLayer::new(CompositeSource::Background),
+                CompositeLayer::new(CompositeSource::Weather),
+                CompositeLayer::new(CompositeSource::View),
+            ],
+        }
+    }
+}
+
+impl CompositeStack {
+    pub fn layer_mut(&mut self, source: &CompositeSource) -> Option<&mut CompositeLayer> {
+        self.layers.iter_mut().find(|layer| layer.source == *source)
+    }
+}
+
+// What fills the bars outside the `Letterbox` rect
+#[derive(Clone, Debug, PartialEq)]
+pub enum LetterboxFill {
+    Color(Color),
+    // The first background layer (or the first upscaled one, e.g. the frame of `PixelPerfect`,
+    // with its edges stretched outwards) blurred by `radius` view pixels
+    BlurredBackground { radius: f32 },
+    // A decorative image stretched over the whole view, with straight alpha as loaded from disk
+    Image(Handle<Image>),
+}
 
-// Original shader
-const COMPOSITE_SHADER_PATH: &str = "shaders/composite.wgsl";
+impl Default for LetterboxFill {
+    fn default() -> Self {
+        Self::Color(Color::BLACK)
+    }
+}
+
+impl LetterboxFill {
+    // Must match the LETTERBOX_* constants in composite.wgsl
+    fn shader_id(&self) -> u32 {
+        match self {
+            Self::Color(_) => 0,
+            Self::BlurredBackground { .. } => 1,
+            Self::Image(_) => 2,
+        }
+    }
+}
+
+// Limits the composited frame to the safe area `rect` (fractions of the view, (0, 0) top
+// left), every layer of the stack alike, and draws `fill` around it. Kept up to date by
+// `ScalingPolicy::FitInside` and `PixelPerfect`, see `SafeArea` for laying out UI in it.
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct Letterbox {
+    pub rect: Rect,
+    pub fill: LetterboxFill,
+}
+
+// Texture of an extracted layer, the background and weather sources already resolved
+#[derive(Clone)]
+enum LayerTexture {
+    View,
+    Image(Handle<Image>),
+}
+
+// Render world side of `CompositeStack`: the textures of the enabled layers, in order, and
+// the incoming background and noise textures of a running `BackgroundTransition`
+#[derive(Component, Clone)]
+pub struct ExtractedCompositeStack {
+    textures: Vec<LayerTexture>,
+    transition: Option<(Handle<Image>, Handle<Image>)>,
+    // Image of `LetterboxFill::Image`
+    letterbox_image: Option<Handle<Image>>,
+}
+
+shader_types! {
+    #[derive(Clone, Copy, Default, ShaderType)]
+    pub(super) struct CompositeLayerUniform {
+        pub(super) blend_mode: u32,
+        pub(super) alpha_mode: u32,
+        pub(super) opacity: f32,
+        pub(super) bloom: f32,
+    }
+
+    // GPU side of `CompositeStack`, see `CompositeSettings` in composite.wgsl
+    #[derive(Component, Clone, Copy, ShaderType)]
+    pub struct CompositeUniform {
+        pub(super) layers: [CompositeLayerUniform; MAX_COMPOSITE_LAYERS],
+        pub(super) layer_count: u32,
+        // Bit per slot of the layers with `CompositeLayer::upscale`
+        pub(super) upscaled_layers: u32,
+        // Slot of the background layer the transition applies to
+        pub(super) transition_layer: u32,
+        // `TransitionStyle::shader_id`, 0 when no transition runs
+        pub(super) transition_style: u32,
+        pub(super) transition_progress: f32,
+        // `LetterboxFill::shader_id`
+        pub(super) letterbox_style: u32,
+        // Slot blurred into the bars by `LetterboxFill::BlurredBackground`
+        pub(super) letterbox_layer: u32,
+        pub(super) letterbox_blur: f32,
+        pub(super) transition_params: Vec4,
+        // Visible part of the view as min xy, max xy, see `Letterbox`
+        pub(super) content_rect: Vec4,
+        // Premultiplied color outside `content_rect`
+        pub(super) letterbox_color: Vec4,
+    }
+}
+
+impl ExtractComponent for CompositeStack {
+    type QueryData = (
+        &'static Self,
+        Option<&'static BackgroundCompositeSource>,
+        Option<&'static WeatherCompositeSource>,
+        Option<&'static BackgroundTransition>,
+        Option<&'static IncomingBackgroundCompositeSource>,
+        Option<&'static Letterbox>,
+    );
+    type QueryFilter = ();
+    type Out = (ExtractedCompositeStack, CompositeUniform);
+
+    fn extract_component(
+        (stack, background, weather, transition, incoming, letterbox): QueryItem<
+            '_,
+            Self::QueryData,
+        >,
+    ) -> Option<Self::Out> {
+        let mut textures = Vec::new();
+        let mut uniform = CompositeUniform {
+            layers: [CompositeLayerUniform::default(); MAX_COMPOSITE_LAYERS],
+            layer_count: 0,
+            upscaled_layers: 0,
+            transition_layer: 0,
+            transition_style: 0,
+            transition_progress: 0.0,
+            letterbox_style: 0,
+            letterbox_layer: 0,
+            letterbox_blur: 0.0,
+            transition_params: Vec4::ZERO,
+            content_rect: Vec4::new(0.0, 0.0, 1.0, 1.0),
+            letterbox_color: Vec4::ZERO,
+        };
+        let mut letterbox_image = None;
+        if let Some(letterbox) = letterbox {
+            uniform.content_rect = Vec4::new(
+                letterbox.rect.min.x,
+                letterbox.rect.min.y,
+                letterbox.rect.max.x,
+                letterbox.rect.max.y,
+            );
+            uniform.letterbox_style = letterbox.fill.shader_id();
+            match &letterbox.fill {
+                LetterboxFill::Color(color) => {
+                    uniform.letterbox_color = premultiply(*color).to_linear().to_vec4();
+                }
+                LetterboxFill::BlurredBackground { radius } => {
+                    uniform.letterbox_blur = radius.max(0.0);
+                }
+                LetterboxFill::Image(handle) => letterbox_image = Some(handle.clone()),
+            }
+        }
+        let mut blur_slot = None;
+        let mut extracted_transition = None;
+
+        let enabled = stack.layers.iter().filter(|layer| layer.enabled);
+        if enabled.clone().count() > MAX_COMPOSITE_LAYERS {
+            warn_once!(
+                "CompositeStack has more than {} enabled layers, the rest is ignored",
+                MAX_COMPOSITE_LAYERS
+            );
+        }
+        for layer in enabled {
+            if textures.len() == MAX_COMPOSITE_LAYERS {
+                break;
+            }
+            // Background and weather are skipped until their camera is linked to this one
+            let texture = match &layer.source {
+                CompositeSource::View => LayerTexture::View,
+                CompositeSource::Background => match background {
+                    Some(background) => LayerTexture::Image(background.handle.clone()),
+                    None => continue,
+                },
+                CompositeSource::Weather => match weather {
+                    Some(weather) => LayerTexture::Image(weather.handle.clone()),
+                    None => continue,
+                },
+                CompositeSource::Image(handle) => LayerTexture::Image(handle.clone()),
+            };
+            // A transition replaces the first background layer with a mix of both backgrounds
+            if layer.source == CompositeSource::Background
+                && extracted_transition.is_none()
+                && let (Some(transition), Some(incoming)) = (transition, incoming)
+            {
+                uniform.transition_layer = textures.len() as u32;
+                uniform.transition_style = transition.style.shader_id();
+                uniform.transition_progress = transition.progress();
+                uniform.transition_params = transition.style.shader_params();
+                extracted_transition = Some((incoming.handle.clone(), transition.style.noise()));
+            }
+            uniform.layers[textures.len()] = CompositeLayerUniform {
+                blend_mode: layer.blend_mode as u32,
+                alpha_mode: layer.alpha_mode as u32,
+                opacity: layer.opacity.clamp(0.0, 1.0),
+                bloom: layer.bloom.max(0.0),
+            };
+            uniform.upscaled_layers |= (layer.upscale as u32) << textures.len();
+            // Bars blur the first background layer, or else the first upscaled one
+            let slot = textures.len() as u32;
+            blur_slot = match (blur_slot, &layer.source) {
+                (Some((_, true)), _) => blur_slot,
+                (_, CompositeSource::Background) => Some((slot, true)),
+                (None, _) if layer.upscale => Some((slot, false)),
+                _ => blur_slot,
+            };
+            textures.push(texture);
+        }
+        uniform.layer_count = textures.len() as u32;
+        uniform.letterbox_layer = blur_slot.map_or(0, |(slot, _)| slot);
+
+        // Nothing to combine, e.g. a camera that opted out of `CompositeBackground` keeps its
+        // stack: the view is left as rendered instead of being copied onto itself. Returning
+        // `None` also drops what earlier frames extracted.
+        if letterbox.is_none()
+            && textures
+                .iter()
+                .all(|texture| matches!(texture, LayerTexture::View))
+        {
+            return None;
+        }
+
+        Some((
+            ExtractedCompositeStack {
+                textures,
+                transition: extracted_transition,
+                letterbox_image,
+            },
+            uniform,
+        ))
+    }
+}
 
 pub struct CompositePlugin;
 
 impl Plugin for CompositePlugin {
     fn build(&self, app: &mut App) {
+        embedded_asset!(app, "shaders/composite.wgsl");
+        app.add_plugins((
+            ExtractComponentPlugin::<CompositeStack>::default(),
+            ExtractComponentPlugin::<CompositePassSettings>::default(),
+            UniformComponentPlugin::<CompositeUniform>::default(),
+        ));
+
         let Some(render_app) = app.get_sub_app_mut(RenderApp) else {
             return;
         };
@@ -57,6 +423,11 @@ impl Plugin for CompositePlugin {
                 crate::cameras::background_lut::BackgroundLutLabel, // Depends on background post-process
                 CompositeLabel,
             )
+            .add_render_graph_edge(
+                Core2d,
+                CompositeLabel, // Bloom sees the composited frame, not only the game layer
+                Node2d::Bloom,
+            )
             .add_render_graph_edge(
                 Core2d,
                 CompositeLabel, // Tonemapping runs AFTER composite
@@ -79,53 +450,106 @@ pub struct CompositeLabel;
 struct CompositeNode;
 
 impl ViewNode for CompositeNode {
-    // Query the main camera's ViewTarget and ensure it's the MainCam
-    type ViewQuery = (&'static ViewTarget,);
+    // Only views with a composite stack: query the view's ViewTarget together with the
+    // extracted layer textures and their settings
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static ExtractedCompositeStack,
+        &'static DynamicUniformIndex<CompositeUniform>,
+        &'static CompositePassSettings,
+    );
 
     fn run(
         &self,
         _graph: &mut RenderGraphContext,
         render_context: &mut RenderContext,
-        view_target: QueryItem<Self::ViewQuery>, // _main_cam ensures we only run for main cam
+        (view_target, stack, settings_index, pass_settings): QueryItem<Self::ViewQuery>,
         world: &World,
     ) -> Result<(), NodeRunError> {
-        info!("Running CompositeNode for view entity");
+        // Only reached for views with a `CompositeStack`
+
+        // The view target is left as rendered, the pipeline and uniforms stay in place
+        if !pass_settings.enabled {
+            return Ok(());
+        }
 
         // Get the pipeline
         let pipeline_cache = world.resource::<PipelineCache>();
         let composite_pipeline = world.resource::<CompositePipeline>();
-        let Some(pipeline) = pipeline_cache.get_render_pipeline(composite_pipeline.pipeline_id)
-        else {
-            info!("Composite pipeline not found or not ready yet.");
-            return Ok(());
+        // HDR views need the variant writing their floating point format
+        let pipeline_id = if view_target.is_hdr() {
+            composite_pipeline.hdr_pipeline_id
+        } else {
+            composite_pipeline.pipeline_id
         };
-
-        // Get the background render target
-        let Some(background_target_res) = world.get_resource::<BackgroundProcessedRenderTarget>()
-        else {
-            error!("BackgroundProcessedRenderTarget resource not found. Cannot composite.");
+        let Some(pipeline) = pipeline_cache.get_render_pipeline(pipeline_id) else {
+            debug!("Composite pipeline not found or not ready yet.");
             return Ok(());
         };
 
-        // Get the GPU textures
-        let gpu_images = world.resource::<RenderAssets<GpuImage>>();
-        let Some(background_gpu_image) = gpu_images.get(&background_target_res.handle) else {
-            info!("Background render target not yet available on GPU.");
+        // Per-view layer settings
+        let settings_uniforms = world.resource::<ComponentUniforms<CompositeUniform>>();
+        let Some(settings_binding) = settings_uniforms.uniforms().binding() else {
             return Ok(());
         };
 
         // Get source/destination textures for the main camera view
-        let post_process = view_target.0.post_process_write();
+        let post_process = view_target.post_process_write();
+
+        // Resolve the layer textures, unused slots and images not yet on the GPU are bound to
+        // a fully transparent texture, which leaves the result unchanged
+        let gpu_images = world.resource::<RenderAssets<GpuImage>>();
+        let fallback = &world.resource::<FallbackImageZero>().texture_view;
+        let mut layer_views = [fallback; MAX_COMPOSITE_LAYERS];
+        for (slot, texture) in layer_views.iter_mut().zip(stack.textures.iter()) {
+            *slot = match texture {
+                LayerTexture::View => post_process.source,
+                LayerTexture::Image(handle) => match gpu_images.get(handle) {
+                    Some(gpu_image) => &gpu_image.texture_view,
+                    None => {
+                        debug!("Composite layer texture not yet available on GPU.");
+                        fallback
+                    }
+                },
+            };
+        }
+
+        // Both are only sampled during a transition
+        let (incoming_view, noise_view) = match &stack.transition {
+            Some((incoming, noise)) => (
+                gpu_images
+                    .get(incoming)
+                    .map_or(fallback, |gpu_image| &gpu_image.texture_view),
+                gpu_images
+                    .get(noise)
+                    .map_or(fallback, |gpu_image| &gpu_image.texture_view),
+            ),
+            None => (fallback, fallback),
+        };
+        let letterbox_image_view = stack
+            .letterbox_image
+            .as_ref()
+            .and_then(|handle| gpu_images.get(handle))
+            .map_or(fallback, |gpu_image| &gpu_image.texture_view);
 
         // Create the bind group with all textures
         let bind_group = render_context.render_device().create_bind_group(
             "composite_bind_group",
             &composite_pipeline.layout,
             &BindGroupEntries::sequential((
-                post_process.source,                    // Main camera view
-                &composite_pipeline.main_sampler,       // Main view sampler
-                &background_gpu_image.texture_view,     // Background texture
-                &composite_pipeline.background_sampler, // Background sampler
+                &composite_pipeline.sampler, // Shared by all layers
+                layer_views[0],
+                layer_views[1],
+                layer_views[2],
+                layer_views[3],
+                layer_views[4],
+                layer_views[5],
+                layer_views[6],
+                layer_views[7],
+                settings_binding.clone(), // Layer settings
+                incoming_view,            // Incoming background of a transition
+                noise_view,               // Dissolve noise
+                letterbox_image_view,     // Bars of `LetterboxFill::Image`
             )),
         );
 
@@ -148,7 +572,7 @@ impl ViewNode for CompositeNode {
 
         // Draw a fullscreen quad
         render_pass.set_render_pipeline(pipeline);
-        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.set_bind_group(0, &bind_group, &[settings_index.index()]);
         render_pass.draw(0..3, 0..1);
 
         Ok(())
@@ -158,67 +582,164 @@ impl ViewNode for CompositeNode {
 #[derive(Resource)]
 struct CompositePipeline {
     layout: BindGroupLayout,
-    main_sampler: Sampler,
-    background_sampler: Sampler,
+    sampler: Sampler,
     pipeline_id: CachedRenderPipelineId,
+    hdr_pipeline_id: CachedRenderPipelineId,
 }
 
 impl FromWorld for CompositePipeline {
     fn from_world(world: &mut World) -> Self {
         let render_device = world.resource::<RenderDevice>();
 
+        let layer_texture = || texture_2d(TextureSampleType::Float { filterable: true });
+
         // Create the bind group layout
         let layout = render_device.create_bind_group_layout(
             "composite_layout",
             &BindGroupLayoutEntries::sequential(
                 ShaderStages::FRAGMENT,
                 (
-                    texture_2d(TextureSampleType::Float { filterable: true }), // Main view texture
-                    sampler(SamplerBindingType::Filtering),                    // Main view sampler
-                    texture_2d(TextureSampleType::Float { filterable: true }), // Background texture
-                    sampler(SamplerBindingType::Filtering),                    // Background sampler
+                    sampler(SamplerBindingType::Filtering), // Shared layer sampler
+                    layer_texture(),                        // Layer 0 (bottom)
+                    layer_texture(),
+                    layer_texture(),
+                    layer_texture(),
+                    layer_texture(),
+                    layer_texture(),
+                    layer_texture(),
+                    layer_texture(),                          // Layer 7 (top)
+                    uniform_buffer::<CompositeUniform>(true), // Layer settings
+                    layer_texture(),                          // Incoming background of a transition
+                    layer_texture(),                          // Dissolve noise
+                    layer_texture(),                          // Letterbox image
                 ),
             ),
         );
 
-        // Create the samplers
-        let main_sampler = render_device.create_sampler(&SamplerDescriptor::default());
-        let background_sampler = render_device.create_sampler(&SamplerDescriptor::default());
+        // Create the sampler
+        let sampler = render_device.create_sampler(&SamplerDescriptor::default());
 
         // Load the shader
         let shader = world.load_asset(COMPOSITE_SHADER_PATH);
 
-        // Create the pipeline
-        let pipeline_id =
-            world
-                .resource_mut::<PipelineCache>()
-                .queue_render_pipeline(RenderPipelineDescriptor {
-                    label: Some("composite_pipeline".into()),
-                    layout: vec![layout.clone()],
-                    vertex: fullscreen_shader_vertex_state(),
-                    fragment: Some(FragmentState {
-                        shader,
-                        shader_defs: vec![],
-                        entry_point: "fragment".into(),
-                        targets: vec![Some(ColorTargetState {
-                            format: TextureFormat::bevy_default(),
-                            // Disable blending; the shader calculates the final value.
-                            blend: None,
-                            write_mask: ColorWrites::ALL,
-                        })],
-                    }),
-                    primitive: PrimitiveState::default(),
-                    depth_stencil: None,
-                    multisample: MultisampleState::default(),
-                    push_constant_ranges: vec![],
-                    zero_initialize_workgroup_memory: false,
-                });
+        // Create the pipelines, one per view target format
+        let descriptor = |label: &'static str, format: TextureFormat, shader_defs| {
+            RenderPipelineDescriptor {
+                label: Some(label.into()),
+                layout: vec![layout.clone()],
+                vertex: fullscreen_shader_vertex_state(),
+                fragment: Some(FragmentState {
+                    shader: shader.clone(),
+                    shader_defs,
+                    entry_point: "fragment".into(),
+                    targets: vec![Some(ColorTargetState {
+                        format,
+                        // Disable blending; the shader calculates the final value.
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                push_constant_ranges: vec![],
+                zero_initialize_workgroup_memory: false,
+            }
+        };
+        let pipeline_cache = world.resource_mut::<PipelineCache>();
+        let pipeline_id = pipeline_cache.queue_render_pipeline(descriptor(
+            "composite_pipeline",
+            TextureFormat::bevy_default(),
+            vec![],
+        ));
+        let hdr_pipeline_id = pipeline_cache.queue_render_pipeline(descriptor(
+            "composite_hdr_pipeline",
+            ViewTarget::TEXTURE_FORMAT_HDR,
+            vec!["HDR".into()],
+        ));
 
         Self {
             layout,
-            main_sampler,
-            background_sampler,
+            sampler,
             pipeline_id,
+            hdr_pipeline_id,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cameras::background_transition::TransitionStyle;
+
+    // composite.wgsl mirrors the ids of the layer settings and the layer limit as constants
+    #[test]
+    fn shader_constants_match() {
+        let shader = include_str!("shaders/composite.wgsl");
+        let constants = [
+            ("MAX_COMPOSITE_LAYERS", MAX_COMPOSITE_LAYERS as u32),
+            ("BLEND_NORMAL", BlendMode::Normal as u32),
+            ("BLEND_MULTIPLY", BlendMode::Multiply as u32),
+            ("BLEND_SCREEN", BlendMode::Screen as u32),
+            ("BLEND_ADD", BlendMode::Add as u32),
+            ("BLEND_OVERLAY", BlendMode::Overlay as u32),
+            ("BLEND_SOFT_LIGHT", BlendMode::SoftLight as u32),
+            ("ALPHA_STRAIGHT", LayerAlphaMode::Straight as u32),
+            ("ALPHA_PREMULTIPLIED", LayerAlphaMode::Premultiplied as u32),
+            (
+                "TRANSITION_CROSSFADE",
+                TransitionStyle::Crossfade.shader_id(),
+            ),
+            (
+                "TRANSITION_WIPE",
+                TransitionStyle::Wipe {
+                    direction: Vec2::X,
+                    softness: 0.1,
+                }
+                .shader_id(),
+            ),
+            (
+                "TRANSITION_DISSOLVE",
+                TransitionStyle::Dissolve {
+                    noise: None,
+                    softness: 0.1,
+                }
+                .shader_id(),
+            ),
+            (
+                "LETTERBOX_COLOR",
+                LetterboxFill::Color(Color::BLACK).shader_id(),
+            ),
+            (
+                "LETTERBOX_BLURRED_BACKGROUND",
+                LetterboxFill::BlurredBackground { radius: 16.0 }.shader_id(),
+            ),
+            (
+                "LETTERBOX_IMAGE",
+                LetterboxFill::Image(Handle::default()).shader_id(),
+            ),
+        ];
+        for (name, value) in constants {
+            let declaration = format!("const {name}: u32 = {value}u;");
+            assert!(shader.contains(&declaration), "missing `{declaration}`");
+        }
+    }
+
+    #[test]
+    fn view_only_stacks_are_not_extracted() {
+        // Background and weather are not linked, as after opting out of `CompositeBackground`
+        let stack = CompositeStack::default();
+        assert!(
+            CompositeStack::extract_component((&stack, None, None, None, None, None)).is_none()
+        );
+
+        let letterbox = Letterbox {
+            rect: Rect::new(0.1, 0.0, 0.9, 1.0),
+            fill: LetterboxFill::default(),
+        };
+        assert!(
+            CompositeStack::extract_component((&stack, None, None, None, None, Some(&letterbox)))
+                .is_some()
+        );
+    }
+}
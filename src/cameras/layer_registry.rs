@@ -0,0 +1,162 @@
+use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
+
+// Name of a layer declared in the `LayerRegistry`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LayerName(pub &'static str);
+
+// Layers declared by the plugins of this crate. Their orders leave gaps so layers of your
+// own (foreground parallax, overlays, debug) can be slotted in between.
+pub const BACKGROUND_LAYER: LayerName = LayerName("background");
+// Background being transitioned to, see `BackgroundTransition`
+pub const INCOMING_BACKGROUND_LAYER: LayerName = LayerName("incoming_background");
+pub const WEATHER_LAYER: LayerName = LayerName("weather");
+pub const GAME_LAYER: LayerName = LayerName("game");
+// Drawn at full resolution over the upscaled game frame of `PixelPerfect`
+pub const UPSCALE_LAYER: LayerName = LayerName("upscale");
+// HUD, drawn last over the finished frame, see `UiCamera`
+pub const UI_LAYER: LayerName = LayerName("ui");
+
+// Where a layer sits: the `Camera::order` of the camera rendering it, and the `RenderLayers`
+// index its content and camera use. The two are independent of each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LayerInfo {
+    pub order: isize,
+    pub render_layer: usize,
+}
+
+// Every named layer of the app. Declare layers with `App::register_layer`, registering a
+// name again replaces its order and index (e.g. to move a built-in layer). Collisions between
+// different names are reported at startup.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct LayerRegistry {
+    layers: Vec<(LayerName, LayerInfo)>,
+}
+
+impl LayerRegistry {
+    pub fn register(&mut self, name: LayerName, info: LayerInfo) {
+        match self
+            .layers
+            .iter_mut()
+            .find(|(existing, _)| *existing == name)
+        {
+            Some((_, existing)) => *existing = info,
+            None => self.layers.push((name, info)),
+        }
+    }
+
+    pub fn get(&self, name: LayerName) -> Option<LayerInfo> {
+        self.layers
+            .iter()
+            .find(|(existing, _)| *existing == name)
+            .map(|(_, info)| *info)
+    }
+
+    // Panics for a layer nobody registered, which is a setup error like a missing plugin
+    fn expect(&self, name: LayerName) -> LayerInfo {
+        self.get(name)
+            .unwrap_or_else(|| panic!("Layer {:?} is not registered", name.0))
+    }
+
+    pub fn order(&self, name: LayerName) -> isize {
+        self.expect(name).order
+    }
+
+    pub fn render_layers(&self, name: LayerName) -> RenderLayers {
+        RenderLayers::layer(self.expect(name).render_layer)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LayerName, LayerInfo)> + '_ {
+        self.layers.iter().copied()
+    }
+
+    // Human readable description of every pair of layers sharing an order or render layer
+    pub fn collisions(&self) -> Vec<String> {
+        let mut collisions = Vec::new();
+        for (index, (name, info)) in self.layers.iter().enumerate() {
+            for (other_name, other_info) in &self.layers[index + 1..] {
+                if info.render_layer == other_info.render_layer {
+                    collisions.push(format!(
+                        "{:?} and {:?} share render layer {}",
+                        name.0, other_name.0, info.render_layer
+                    ));
+                }
+                if info.order == other_info.order {
+                    collisions.push(format!(
+                        "{:?} and {:?} share camera order {}",
+                        name.0, other_name.0, info.order
+                    ));
+                }
+            }
+        }
+        collisions
+    }
+}
+
+pub trait RegisterLayerExt {
+    // Declares (or moves) the layer `name`, see `LayerRegistry`
+    fn register_layer(&mut self, name: LayerName, order: isize, render_layer: usize) -> &mut Self;
+}
+
+impl RegisterLayerExt for App {
+    fn register_layer(&mut self, name: LayerName, order: isize, render_layer: usize) -> &mut Self {
+        if !self.world().contains_resource::<LayerRegistry>() {
+            self.init_resource::<LayerRegistry>()
+                .add_systems(PreStartup, validate_layer_registry);
+        }
+        self.world_mut().resource_mut::<LayerRegistry>().register(
+            name,
+            LayerInfo {
+                order,
+                render_layer,
+            },
+        );
+        self
+    }
+}
+
+// Refuses to start with colliding layers, which would otherwise show up as content rendered
+// by the wrong camera or cameras fighting over the same order
+fn validate_layer_registry(registry: Res<LayerRegistry>) {
+    let collisions = registry.collisions();
+    if !collisions.is_empty() {
+        panic!("Colliding layers:\n{}", collisions.join("\n"));
+    }
+    for (name, info) in registry.iter() {
+        info!(
+            "Layer {:?}: order {}, render layer {}",
+            name.0, info.order, info.render_layer
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(order: isize, render_layer: usize) -> LayerInfo {
+        LayerInfo {
+            order,
+            render_layer,
+        }
+    }
+
+    #[test]
+    fn registering_again_replaces() {
+        let mut registry = LayerRegistry::default();
+        registry.register(GAME_LAYER, info(30, 3));
+        registry.register(GAME_LAYER, info(35, 5));
+        assert_eq!(registry.get(GAME_LAYER), Some(info(35, 5)));
+        assert_eq!(registry.iter().count(), 1);
+        assert!(registry.collisions().is_empty());
+    }
+
+    #[test]
+    fn detects_shared_render_layer_and_order() {
+        let mut registry = LayerRegistry::default();
+        registry.register(BACKGROUND_LAYER, info(0, 0));
+        registry.register(GAME_LAYER, info(30, 0));
+        registry.register(UI_LAYER, info(30, 4));
+        assert_eq!(registry.collisions().len(), 2);
+    }
+}
@@ -0,0 +1,219 @@
+use bevy::prelude::*;
+use bevy::render::camera::{RenderTarget, ScalingMode};
+use bevy::render::render_resource::Extent3d;
+use bevy::render::view::RenderLayers;
+use bevy::ui::RelativeCursorPosition;
+
+use super::background_camera::{
+    BackgroundLutSource, BackgroundProcessedRenderTarget, BackgroundRenderTarget,
+    create_background_target_image,
+};
+use super::background_lut::IdentityLut;
+use super::camera_plugin::GameCamera;
+use super::layer_registry::{BACKGROUND_LAYER, GAME_LAYER, LayerRegistry};
+
+// Overview of the game world rendered into a small image, graded with its own LUT.
+// The entity becomes a camera with the same render target pair and LUT pass as the background
+// camera, the graded image is its `BackgroundProcessedRenderTarget`. Show it with a
+// `MinimapDisplay` node.
+#[derive(Component, Clone, Debug)]
+pub struct Minimap {
+    // Size of the minimap image in pixels
+    pub size: UVec2,
+    // Game world area shown, stretched over the whole image
+    pub area: Rect,
+    // Grade of the minimap, `None` keeps the colors as rendered
+    pub lut: Option<Handle<Image>>,
+    // Also draw the background layer under the game layer
+    pub show_background: bool,
+}
+
+impl Minimap {
+    pub fn new(size: UVec2, area: Rect) -> Self {
+        Self {
+            size,
+            area,
+            lut: None,
+            show_background: false,
+        }
+    }
+
+    pub fn with_lut(mut self, lut: Handle<Image>) -> Self {
+        self.lut = Some(lut);
+        self
+    }
+
+    pub fn with_background(mut self) -> Self {
+        self.show_background = true;
+        self
+    }
+}
+
+// UI node showing the graded image of the `minimap` entity. Pressing (or dragging) on it
+// centers the game camera on that spot of the minimap.
+#[derive(Component, Clone, Copy, Debug)]
+#[require(ImageNode, Interaction, RelativeCursorPosition)]
+pub struct MinimapDisplay {
+    pub minimap: Entity,
+}
+
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                sync_minimaps,
+                update_minimap_displays,
+                move_camera_from_minimap,
+            )
+                .chain(),
+        );
+    }
+}
+
+fn minimap_render_layers(minimap: &Minimap, layers: &LayerRegistry) -> RenderLayers {
+    let game = layers.render_layers(GAME_LAYER);
+    if minimap.show_background {
+        game.union(&layers.render_layers(BACKGROUND_LAYER))
+    } else {
+        game
+    }
+}
+
+// Turns new minimaps into cameras with their own target pair, and follows later changes
+// of size, area, grade and layers
+#[allow(clippy::type_complexity)]
+fn sync_minimaps(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    identity_lut: Res<IdentityLut>,
+    layers: Res<LayerRegistry>,
+    mut minimaps: Query<
+        (
+            Entity,
+            &Minimap,
+            Option<&BackgroundRenderTarget>,
+            Option<&BackgroundProcessedRenderTarget>,
+            Option<&mut BackgroundLutSource>,
+        ),
+        Changed<Minimap>,
+    >,
+) {
+    for (entity, minimap, render_target, processed_target, lut_source) in minimaps.iter_mut() {
+        let size = Extent3d {
+            width: minimap.size.x.max(1),
+            height: minimap.size.y.max(1),
+            ..default()
+        };
+        let lut_texture = minimap
+            .lut
+            .clone()
+            .unwrap_or_else(|| identity_lut.handle.clone());
+        let projection = OrthographicProjection {
+            scaling_mode: ScalingMode::Fixed {
+                width: minimap.area.width(),
+                height: minimap.area.height(),
+            },
+            ..OrthographicProjection::default_2d()
+        };
+        let transform = Transform::from_translation(minimap.area.center().extend(0.0));
+
+        match (render_target, processed_target, lut_source) {
+            (Some(render_target), Some(processed_target), Some(mut lut_source)) => {
+                for handle in [&render_target.handle, &processed_target.handle] {
+                    if images
+                        .get(handle)
+                        .map(|image| image.texture_descriptor.size)
+                        != Some(size)
+                        && let Some(image) = images.get_mut(handle)
+                    {
+                        info!("Resizing minimap target to {}x{}", size.width, size.height);
+                        image.resize(size);
+                    }
+                }
+                if lut_source.lut_texture != lut_texture {
+                    lut_source.lut_texture = lut_texture;
+                }
+                commands.entity(entity).insert((
+                    projection,
+                    transform,
+                    minimap_render_layers(minimap, &layers),
+                ));
+            }
+            _ => {
+                info!(
+                    "Setting up minimap camera: {}x{} showing {:?}",
+                    size.width, size.height, minimap.area
+                );
+                let render_target_handle = images.add(create_background_target_image(
+                    "minimap_render_target",
+                    size,
+                ));
+                let processed_target_handle = images.add(create_background_target_image(
+                    "minimap_processed_render_target",
+                    size,
+                ));
+                commands.entity(entity).insert((
+                    Camera2d,
+                    Camera {
+                        // Renders to its own image, the order only has to be unique per target
+                        order: layers.order(GAME_LAYER),
+                        target: RenderTarget::Image(render_target_handle.clone()),
+                        clear_color: ClearColorConfig::Custom(Color::srgba(0.0, 0.0, 0.0, 0.0)),
+                        ..default()
+                    },
+                    projection,
+                    transform,
+                    minimap_render_layers(minimap, &layers),
+                    BackgroundLutSource { lut_texture },
+                    BackgroundRenderTarget {
+                        handle: render_target_handle,
+                    },
+                    BackgroundProcessedRenderTarget {
+                        handle: processed_target_handle,
+                    },
+                ));
+            }
+        }
+    }
+}
+
+// Points every display at the graded image of its minimap
+fn update_minimap_displays(
+    mut displays: Query<(&MinimapDisplay, &mut ImageNode)>,
+    minimaps: Query<&BackgroundProcessedRenderTarget, With<Minimap>>,
+) {
+    for (display, mut image_node) in displays.iter_mut() {
+        let Ok(processed_target) = minimaps.get(display.minimap) else {
+            continue;
+        };
+        if image_node.image != processed_target.handle {
+            image_node.image = processed_target.handle.clone();
+        }
+    }
+}
+
+// Centers the game camera on the minimap spot under the cursor while a display is pressed.
+// An `RtsCameraController` clamps the result to its bounds.
+fn move_camera_from_minimap(
+    displays: Query<(&MinimapDisplay, &Interaction, &RelativeCursorPosition)>,
+    minimaps: Query<&Minimap>,
+    mut game_cameras: Query<&mut Transform, With<GameCamera>>,
+) {
+    for (display, interaction, cursor) in displays.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let (Some(uv), Ok(minimap)) = (cursor.normalized, minimaps.get(display.minimap)) else {
+            continue;
+        };
+        // The display is top down, the world y up
+        let uv = uv.clamp(Vec2::ZERO, Vec2::ONE);
+        let target = minimap.area.min + Vec2::new(uv.x, 1.0 - uv.y) * minimap.area.size();
+        for mut transform in game_cameras.iter_mut() {
+            transform.translation = target.extend(transform.translation.z);
+        }
+    }
+}
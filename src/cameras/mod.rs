@@ -1,4 +1,30 @@
+// Declares the `ShaderType` structs of a module in a nested module that allows dead code, and
+// re-exports them. Newer compilers report the layout checks the derive generates as unused
+// functions, and since they are emitted next to the struct an allow on the struct can't reach them.
+macro_rules! shader_types {
+    ($($item:item)*) => {
+        #[allow(dead_code)]
+        mod shader_types {
+            use super::*;
+
+            $($item)*
+        }
+        pub use shader_types::*;
+    };
+}
+
+pub mod animated_background;
 pub mod background_camera;
+pub mod background_fit;
 pub mod background_lut;
+pub mod background_transition;
+pub mod blend_mode;
 pub mod camera_plugin;
+pub mod camera_shake;
 pub mod composite_pass;
+pub mod layer_registry;
+pub mod minimap;
+pub mod procedural_sky;
+pub mod tiling_background;
+pub mod view_coordinates;
+pub mod weather;
@@ -0,0 +1,194 @@
+use bevy::asset::embedded_asset;
+use bevy::prelude::*;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef, ShaderType};
+use bevy::sprite::{AlphaMode2d, Material2d, Material2dPlugin};
+
+use super::background_camera::FillBackgroundTarget;
+use super::layer_registry::{BACKGROUND_LAYER, LayerRegistry};
+
+const PROCEDURAL_SKY_SHADER_PATH: &str =
+    "embedded://bevy_background_camera/cameras/shaders/procedural_sky.wgsl";
+
+// A sun or moon drawn into the procedural sky
+#[derive(Clone, Copy, Debug)]
+pub struct SkyDisc {
+    // Position on screen, (0, 0) bottom left and (1, 1) top right
+    pub position: Vec2,
+    // Radius as a fraction of the sky height
+    pub radius: f32,
+    pub color: Color,
+    // Size of the halo relative to the radius
+    pub glow: f32,
+}
+
+// Procedural sky drawn over the whole background target on the background layer.
+// It goes through the background LUT like any other background content. Put it at a
+// lower z than bitmap backgrounds to sit under them, or use it on its own.
+#[derive(Component, Clone, Debug)]
+pub struct ProceduralSky {
+    pub zenith_color: Color,
+    pub horizon_color: Color,
+    pub sun: Option<SkyDisc>,
+    pub moon: Option<SkyDisc>,
+    // Fraction (0..1) of the star grid cells holding a star, 0 disables the stars
+    pub star_density: f32,
+    pub star_brightness: f32,
+    pub twinkle_speed: f32,
+    // Fraction (0..1) of the sky covered by clouds, 0 disables the clouds
+    pub cloud_coverage: f32,
+    pub cloud_color: Color,
+    // Noise frequency of the clouds, higher gives smaller clouds
+    pub cloud_scale: f32,
+    // Cloud drift in sky heights per second
+    pub cloud_velocity: Vec2,
+}
+
+impl Default for ProceduralSky {
+    fn default() -> Self {
+        Self {
+            zenith_color: Color::srgb(0.18, 0.38, 0.78),
+            horizon_color: Color::srgb(0.72, 0.84, 0.95),
+            sun: Some(SkyDisc {
+                position: Vec2::new(0.75, 0.8),
+                radius: 0.05,
+                color: Color::srgb(1.0, 0.95, 0.8),
+                glow: 1.5,
+            }),
+            moon: None,
+            star_density: 0.0,
+            star_brightness: 1.0,
+            twinkle_speed: 3.0,
+            cloud_coverage: 0.4,
+            cloud_color: Color::srgba(1.0, 1.0, 1.0, 0.9),
+            cloud_scale: 3.0,
+            cloud_velocity: Vec2::new(0.01, 0.0),
+        }
+    }
+}
+
+shader_types! {
+    #[derive(Clone, Copy, Debug, Default, ShaderType)]
+    pub struct ProceduralSkyParams {
+        pub zenith_color: Vec4,
+        pub horizon_color: Vec4,
+        pub sun_color: Vec4,
+        pub sun_disc: Vec4,
+        pub moon_color: Vec4,
+        pub moon_disc: Vec4,
+        pub stars: Vec4,
+        pub clouds: Vec4,
+        pub cloud_color: Vec4,
+        pub quad_size: Vec2,
+    }
+}
+
+impl ProceduralSkyParams {
+    fn new(sky: &ProceduralSky, quad_size: Vec2) -> Self {
+        // Disabled discs are sent with a zero alpha so the shader adds no light
+        let disc = |disc: Option<SkyDisc>| match disc {
+            Some(disc) => (
+                LinearRgba::from(disc.color).to_vec4(),
+                disc.position.extend(disc.radius).extend(disc.glow),
+            ),
+            None => (Vec4::ZERO, Vec4::ZERO),
+        };
+        let (sun_color, sun_disc) = disc(sky.sun);
+        let (moon_color, moon_disc) = disc(sky.moon);
+        Self {
+            zenith_color: LinearRgba::from(sky.zenith_color).to_vec4(),
+            horizon_color: LinearRgba::from(sky.horizon_color).to_vec4(),
+            sun_color,
+            sun_disc,
+            moon_color,
+            moon_disc,
+            stars: Vec4::new(
+                sky.star_density,
+                sky.star_brightness,
+                sky.twinkle_speed,
+                0.0,
+            ),
+            clouds: Vec4::new(
+                sky.cloud_coverage,
+                sky.cloud_scale,
+                sky.cloud_velocity.x,
+                sky.cloud_velocity.y,
+            ),
+            cloud_color: LinearRgba::from(sky.cloud_color).to_vec4(),
+            quad_size,
+        }
+    }
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Clone, Debug)]
+pub struct ProceduralSkyMaterial {
+    #[uniform(0)]
+    pub params: ProceduralSkyParams,
+}
+
+impl Material2d for ProceduralSkyMaterial {
+    fn fragment_shader() -> ShaderRef {
+        PROCEDURAL_SKY_SHADER_PATH.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode2d {
+        AlphaMode2d::Opaque
+    }
+}
+
+// The material driving the sky quad of a `ProceduralSky` entity
+#[derive(Component)]
+struct ProceduralSkyState {
+    material: Handle<ProceduralSkyMaterial>,
+}
+
+pub struct ProceduralSkyPlugin;
+
+impl Plugin for ProceduralSkyPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "shaders/procedural_sky.wgsl");
+        app.add_plugins(Material2dPlugin::<ProceduralSkyMaterial>::default())
+            .add_systems(
+                Update,
+                (spawn_procedural_skies, update_procedural_skies).chain(),
+            );
+    }
+}
+
+// Gives every new sky its quad, material and the background render layer
+fn spawn_procedural_skies(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ProceduralSkyMaterial>>,
+    layers: Res<LayerRegistry>,
+    skies: Query<(Entity, &ProceduralSky), Added<ProceduralSky>>,
+) {
+    for (entity, sky) in skies.iter() {
+        let material = materials.add(ProceduralSkyMaterial {
+            params: ProceduralSkyParams::new(sky, Vec2::ONE),
+        });
+        commands.entity(entity).insert((
+            Mesh2d(meshes.add(Rectangle::new(1.0, 1.0))),
+            MeshMaterial2d(material.clone()),
+            FillBackgroundTarget,
+            ProceduralSkyState { material },
+        ));
+        // Kept when already set, e.g. to the incoming background of a transition
+        commands
+            .entity(entity)
+            .insert_if_new(layers.render_layers(BACKGROUND_LAYER));
+    }
+}
+
+type SkyChangedFilter = Or<(Changed<ProceduralSky>, Changed<Transform>)>;
+
+// Pushes the component parameters (and the covered size, for the aspect ratio) to the material
+fn update_procedural_skies(
+    mut materials: ResMut<Assets<ProceduralSkyMaterial>>,
+    skies: Query<(&ProceduralSky, &ProceduralSkyState, &Transform), SkyChangedFilter>,
+) {
+    for (sky, state, transform) in skies.iter() {
+        if let Some(material) = materials.get_mut(&state.material) {
+            material.params = ProceduralSkyParams::new(sky, transform.scale.truncate());
+        }
+    }
+}
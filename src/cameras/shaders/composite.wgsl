@@ -0,0 +1,250 @@
+// Nightdrawn-Tower-Defense/client/assets/shaders/composite.wgsl
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+@group(0) @binding(0) var layer_sampler: sampler; // Shared by all layers
+@group(0) @binding(1) var layer_0: texture_2d<f32>;  // Bottom layer of the stack
+@group(0) @binding(2) var layer_1: texture_2d<f32>;
+@group(0) @binding(3) var layer_2: texture_2d<f32>;
+@group(0) @binding(4) var layer_3: texture_2d<f32>;
+@group(0) @binding(5) var layer_4: texture_2d<f32>;
+@group(0) @binding(6) var layer_5: texture_2d<f32>;
+@group(0) @binding(7) var layer_6: texture_2d<f32>;
+@group(0) @binding(8) var layer_7: texture_2d<f32>;  // Top layer of the stack
+
+// Must match MAX_COMPOSITE_LAYERS in composite_pass.rs
+const MAX_COMPOSITE_LAYERS: u32 = 8u;
+
+struct CompositeLayer {
+    blend_mode: u32, // Blend mode of the layer over everything below it
+    alpha_mode: u32, // How the colors of the layer texture are stored
+    opacity: f32,
+    bloom: f32,      // Extra HDR brightness, only applied on HDR views
+};
+
+struct CompositeSettings {
+    layers: array<CompositeLayer, MAX_COMPOSITE_LAYERS>,
+    layer_count: u32, // Used layers, unused slots are bound to a transparent texture
+    upscaled_layers: u32, // Bit per slot stretched over content_rect with nearest filtering
+    transition_layer: u32,    // Slot of the background layer a transition applies to
+    transition_style: u32,    // One of the TRANSITION_* constants
+    transition_progress: f32, // Eased, 0 outgoing to 1 incoming
+    letterbox_style: u32,     // One of the LETTERBOX_* constants
+    letterbox_layer: u32,     // Slot blurred into the bars
+    letterbox_blur: f32,      // Blur radius in view pixels
+    transition_params: vec4<f32>, // xy wipe direction in texture space, z edge softness
+    content_rect: vec4<f32>,      // Visible uv range as min xy, max xy, bars outside
+    letterbox_color: vec4<f32>,   // Premultiplied bar color
+};
+@group(0) @binding(9) var<uniform> settings: CompositeSettings;
+@group(0) @binding(10) var incoming_background: texture_2d<f32>; // Background transitioned to
+@group(0) @binding(11) var transition_noise: texture_2d<f32>;    // Dissolve order
+@group(0) @binding(12) var letterbox_image: texture_2d<f32>;     // Bars of LETTERBOX_IMAGE
+
+// Transition styles, must match `TransitionStyle::shader_id` in background_transition.rs
+const TRANSITION_NONE: u32 = 0u;
+const TRANSITION_CROSSFADE: u32 = 1u;
+const TRANSITION_WIPE: u32 = 2u;
+const TRANSITION_DISSOLVE: u32 = 3u;
+
+// Mixes the outgoing background into the incoming one, both premultiplied
+fn apply_transition(outgoing: vec4<f32>, incoming: vec4<f32>, noise: f32, uv: vec2<f32>) -> vec4<f32> {
+    let t = settings.transition_progress;
+    let softness = settings.transition_params.z;
+    // The edge travels past 1 by the softness so the last pixels fully switch over
+    let edge = t * (1.0 + softness);
+    switch settings.transition_style {
+        case TRANSITION_CROSSFADE: {
+            return mix(outgoing, incoming, t);
+        }
+        case TRANSITION_WIPE: {
+            // Position along the wipe direction, 0 on the side it starts from and 1 opposite
+            let direction = settings.transition_params.xy;
+            let along = dot(uv - 0.5, direction) / (abs(direction.x) + abs(direction.y)) + 0.5;
+            return mix(outgoing, incoming, 1.0 - smoothstep(edge - softness, edge, along));
+        }
+        case TRANSITION_DISSOLVE: {
+            return mix(outgoing, incoming, 1.0 - smoothstep(edge - softness, edge, noise));
+        }
+        default: {
+            return outgoing;
+        }
+    }
+}
+
+// Blend modes, must match the discriminants of `BlendMode` in blend_mode.rs
+const BLEND_NORMAL: u32 = 0u;
+const BLEND_MULTIPLY: u32 = 1u;
+const BLEND_SCREEN: u32 = 2u;
+const BLEND_ADD: u32 = 3u;
+const BLEND_OVERLAY: u32 = 4u;
+const BLEND_SOFT_LIGHT: u32 = 5u;
+
+// Alpha modes, must match the discriminants of `LayerAlphaMode` in blend_mode.rs
+const ALPHA_STRAIGHT: u32 = 0u;
+const ALPHA_PREMULTIPLIED: u32 = 1u;
+
+fn to_premultiplied(alpha_mode: u32, color: vec4<f32>) -> vec4<f32> {
+    if alpha_mode == ALPHA_STRAIGHT {
+        return vec4<f32>(color.rgb * color.a, color.a);
+    }
+    return color;
+}
+
+// Straight color of a premultiplied one, black where fully transparent
+fn unpremultiply(color: vec4<f32>) -> vec3<f32> {
+    return select(vec3<f32>(0.0), color.rgb / color.a, color.a > 0.0);
+}
+
+fn overlay(backdrop: vec3<f32>, source: vec3<f32>) -> vec3<f32> {
+    let low = 2.0 * backdrop * source;
+    let high = 1.0 - 2.0 * (1.0 - backdrop) * (1.0 - source);
+    return select(high, low, backdrop <= vec3<f32>(0.5));
+}
+
+// W3C soft light
+fn soft_light(backdrop: vec3<f32>, source: vec3<f32>) -> vec3<f32> {
+    let d = select(
+        sqrt(backdrop),
+        ((16.0 * backdrop - 12.0) * backdrop + 4.0) * backdrop,
+        backdrop <= vec3<f32>(0.25),
+    );
+    let darken = backdrop - (1.0 - 2.0 * source) * backdrop * (1.0 - backdrop);
+    let lighten = backdrop + (2.0 * source - 1.0) * (d - backdrop);
+    return select(lighten, darken, source <= vec3<f32>(0.5));
+}
+
+// Separable blend function B(backdrop, source) on straight colors
+fn blend_rgb(mode: u32, backdrop: vec3<f32>, source: vec3<f32>) -> vec3<f32> {
+    switch mode {
+        case BLEND_MULTIPLY: { return backdrop * source; }
+        case BLEND_SCREEN: { return backdrop + source - backdrop * source; }
+        case BLEND_ADD: { return min(backdrop + source, vec3<f32>(1.0)); }
+        case BLEND_OVERLAY: { return overlay(backdrop, source); }
+        case BLEND_SOFT_LIGHT: { return soft_light(backdrop, source); }
+        default: { return source; }
+    }
+}
+
+// Composites `source` over `backdrop` with `mode`, both and the result premultiplied.
+// W3C compositing: co = cs * (1 - ab) + cb * (1 - as) + as * ab * B(Cb, Cs), so where the
+// backdrop is transparent the source shows unblended.
+fn blend_layer(mode: u32, backdrop: vec4<f32>, source: vec4<f32>) -> vec4<f32> {
+    let blended = blend_rgb(mode, unpremultiply(backdrop), unpremultiply(source));
+    let rgb = source.rgb * (1.0 - backdrop.a)
+        + backdrop.rgb * (1.0 - source.a)
+        + blended * source.a * backdrop.a;
+    return vec4<f32>(rgb, source.a + backdrop.a * (1.0 - source.a));
+}
+
+// Color of the layer in `slot` at `uv`. Upscaled layers are read texel by texel, so
+// integer scale factors keep hard pixel edges.
+fn sample_layer(texture: texture_2d<f32>, slot: u32, uv: vec2<f32>) -> vec4<f32> {
+    let filtered = textureSample(texture, layer_sampler, uv);
+    if (settings.upscaled_layers & (1u << slot)) == 0u {
+        return filtered;
+    }
+    let rect = settings.content_rect;
+    let content_uv = (uv - rect.xy) / (rect.zw - rect.xy);
+    let size = vec2<i32>(textureDimensions(texture));
+    let texel = clamp(vec2<i32>(floor(content_uv * vec2<f32>(size))), vec2<i32>(0), size - 1);
+    return textureLoad(texture, texel, 0);
+}
+
+// Bar fills, must match `LetterboxFill::shader_id` in composite_pass.rs
+const LETTERBOX_COLOR: u32 = 0u;
+const LETTERBOX_BLURRED_BACKGROUND: u32 = 1u;
+const LETTERBOX_IMAGE: u32 = 2u;
+
+// Layer texture of any slot, without implicit derivatives so it works in the bars only
+fn sample_slot(slot: u32, uv: vec2<f32>) -> vec4<f32> {
+    switch slot {
+        case 0u: { return textureSampleLevel(layer_0, layer_sampler, uv, 0.0); }
+        case 1u: { return textureSampleLevel(layer_1, layer_sampler, uv, 0.0); }
+        case 2u: { return textureSampleLevel(layer_2, layer_sampler, uv, 0.0); }
+        case 3u: { return textureSampleLevel(layer_3, layer_sampler, uv, 0.0); }
+        case 4u: { return textureSampleLevel(layer_4, layer_sampler, uv, 0.0); }
+        case 5u: { return textureSampleLevel(layer_5, layer_sampler, uv, 0.0); }
+        case 6u: { return textureSampleLevel(layer_6, layer_sampler, uv, 0.0); }
+        default: { return textureSampleLevel(layer_7, layer_sampler, uv, 0.0); }
+    }
+}
+
+// Color of the bars outside content_rect, `pixel` is the uv size of one view pixel
+fn letterbox_fill(uv: vec2<f32>, pixel: vec2<f32>) -> vec4<f32> {
+    switch settings.letterbox_style {
+        case LETTERBOX_BLURRED_BACKGROUND: {
+            let slot = settings.letterbox_layer;
+            var center = uv;
+            var step = pixel * settings.letterbox_blur / 3.0;
+            // An upscaled layer only covers the content rect, its edges are stretched outwards
+            if (settings.upscaled_layers & (1u << slot)) != 0u {
+                let rect = settings.content_rect;
+                center = clamp((uv - rect.xy) / (rect.zw - rect.xy), vec2<f32>(0.0), vec2<f32>(1.0));
+                step = step / (rect.zw - rect.xy);
+            }
+            // 7x7 gaussian taps spread over the radius
+            var sum = vec4<f32>(0.0);
+            var total = 0.0;
+            for (var y = -3; y <= 3; y++) {
+                for (var x = -3; x <= 3; x++) {
+                    let weight = exp(-f32(x * x + y * y) / 8.0);
+                    let color = sample_slot(slot, center + vec2<f32>(f32(x), f32(y)) * step);
+                    sum += to_premultiplied(settings.layers[slot].alpha_mode, color) * weight;
+                    total += weight;
+                }
+            }
+            return sum / total;
+        }
+        case LETTERBOX_IMAGE: {
+            let color = textureSampleLevel(letterbox_image, layer_sampler, uv, 0.0);
+            return to_premultiplied(ALPHA_STRAIGHT, color);
+        }
+        default: {
+            return settings.letterbox_color;
+        }
+    }
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    // Sample every slot up front, sampling must happen in uniform control flow
+    var colors = array<vec4<f32>, MAX_COMPOSITE_LAYERS>(
+        sample_layer(layer_0, 0u, in.uv),
+        sample_layer(layer_1, 1u, in.uv),
+        sample_layer(layer_2, 2u, in.uv),
+        sample_layer(layer_3, 3u, in.uv),
+        sample_layer(layer_4, 4u, in.uv),
+        sample_layer(layer_5, 5u, in.uv),
+        sample_layer(layer_6, 6u, in.uv),
+        sample_layer(layer_7, 7u, in.uv),
+    );
+    let incoming = textureSample(incoming_background, layer_sampler, in.uv);
+    let noise = textureSample(transition_noise, layer_sampler, in.uv).r;
+    let pixel = fwidth(in.uv);
+
+    // Each layer goes over everything below it. Everything works on premultiplied colors,
+    // straight inputs are converted first. With BLEND_NORMAL this is the standard
+    // premultiplied "over":
+    // C_out = C_src + C_dst * (1 - A_src)
+    // A_out = A_src + A_dst * (1 - A_src)
+    // The result stays premultiplied, ready for a transparent window.
+    var result = vec4<f32>(0.0);
+    for (var i = 0u; i < min(settings.layer_count, MAX_COMPOSITE_LAYERS); i++) {
+        let layer = settings.layers[i];
+        var color = to_premultiplied(layer.alpha_mode, colors[i]);
+        if settings.transition_style != TRANSITION_NONE && i == settings.transition_layer {
+            color = apply_transition(color, incoming, noise, in.uv);
+        }
+#ifdef HDR
+        // Brighter than white so the layer reaches the bloom threshold
+        color = vec4<f32>(color.rgb * (1.0 + layer.bloom), color.a);
+#endif
+        let source = color * layer.opacity;
+        result = blend_layer(layer.blend_mode, result, source);
+    }
+    // Letterbox bars, checked after sampling so the texture reads stay in uniform control flow
+    if any(in.uv < settings.content_rect.xy) || any(in.uv > settings.content_rect.zw) {
+        return letterbox_fill(in.uv, pixel);
+    }
+    return result;
+}
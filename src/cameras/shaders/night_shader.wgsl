@@ -80,9 +80,14 @@ fn sample_lut_trilinear(color: vec3<f32>) -> vec3<f32> {
 // --- Fragment Shader Entry Point ---
 @fragment
 fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    // The layer targets hold premultiplied colors, grade the straight color and premultiply
+    // again so semi-transparent pixels keep their hue
     let original_color = textureSample(screen_texture, screen_sampler, in.uv);
+    if original_color.a <= 0.0 {
+        return vec4<f32>(0.0);
+    }
         // Apply the LUT
-        let lut_result_rgb = sample_lut_trilinear(original_color.rgb);
+        let lut_result_rgb = sample_lut_trilinear(original_color.rgb / original_color.a);
         // Combine LUT RGB with original alpha
-        return vec4<f32>(lut_result_rgb, original_color.a);
+        return vec4<f32>(lut_result_rgb * original_color.a, original_color.a);
 }
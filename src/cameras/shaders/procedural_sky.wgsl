@@ -0,0 +1,95 @@
+#import bevy_sprite::{
+    mesh2d_vertex_output::VertexOutput,
+    mesh2d_view_bindings::globals,
+}
+
+struct ProceduralSkyParams {
+    zenith_color: vec4<f32>,
+    horizon_color: vec4<f32>,
+    sun_color: vec4<f32>,   // Alpha 0 disables the sun
+    sun_disc: vec4<f32>,    // xy: position (0..1, y up), z: radius (fraction of height), w: glow
+    moon_color: vec4<f32>,  // Alpha 0 disables the moon
+    moon_disc: vec4<f32>,   // Same layout as sun_disc
+    stars: vec4<f32>,       // x: density (0..1), y: brightness, z: twinkle speed
+    clouds: vec4<f32>,      // x: coverage (0..1), y: scale, zw: velocity in sky heights per second
+    cloud_color: vec4<f32>,
+    quad_size: vec2<f32>,   // Covered area in world units, used for the aspect ratio
+};
+
+@group(2) @binding(0) var<uniform> sky: ProceduralSkyParams;
+
+// Cells per sky height in the star grid
+const STAR_GRID: f32 = 90.0;
+
+fn hash21(p: vec2<f32>) -> f32 {
+    var p3 = fract(vec3<f32>(p.xyx) * 0.1031);
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+fn value_noise(p: vec2<f32>) -> f32 {
+    let cell = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let a = hash21(cell);
+    let b = hash21(cell + vec2<f32>(1.0, 0.0));
+    let c = hash21(cell + vec2<f32>(0.0, 1.0));
+    let d = hash21(cell + vec2<f32>(1.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+fn fbm(p: vec2<f32>) -> f32 {
+    var value = 0.0;
+    var amplitude = 0.5;
+    var q = p;
+    for (var octave = 0; octave < 5; octave++) {
+        value += amplitude * value_noise(q);
+        q = q * 2.03 + vec2<f32>(17.0, 9.0);
+        amplitude *= 0.5;
+    }
+    return value;
+}
+
+// Light contributed by a disc (sun or moon) with a soft halo around it
+fn disc_light(p: vec2<f32>, aspect: f32, disc: vec4<f32>, color: vec4<f32>) -> vec3<f32> {
+    let center = vec2<f32>(disc.x * aspect, disc.y);
+    let distance = length(p - center);
+    let radius = max(disc.z, 1e-4);
+    let core = 1.0 - smoothstep(radius * 0.9, radius, distance);
+    let halo = exp(-max(distance - radius, 0.0) / (radius * max(disc.w, 1e-4))) * 0.5;
+    return color.rgb * (core + halo) * color.a;
+}
+
+fn star_light(p: vec2<f32>) -> f32 {
+    let grid = p * STAR_GRID;
+    let cell = floor(grid);
+    let seed = hash21(cell);
+    // Only a `density` fraction of the cells holds a star
+    if seed > sky.stars.x {
+        return 0.0;
+    }
+    let star_position = vec2<f32>(hash21(cell + 13.7), hash21(cell + 71.3)) * 0.6 + 0.2;
+    let distance = length(fract(grid) - star_position);
+    let twinkle = 0.6 + 0.4 * sin(globals.time * sky.stars.z + seed * 628.3);
+    return (1.0 - smoothstep(0.0, 0.12, distance)) * twinkle * sky.stars.y;
+}
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    // Sky coordinates: y goes from 0 at the bottom to 1 at the top, x keeps the aspect ratio
+    let aspect = sky.quad_size.x / max(sky.quad_size.y, 1.0);
+    let sky_uv = vec2<f32>(mesh.uv.x, 1.0 - mesh.uv.y);
+    let p = vec2<f32>(sky_uv.x * aspect, sky_uv.y);
+
+    var color = mix(sky.horizon_color.rgb, sky.zenith_color.rgb, smoothstep(0.0, 1.0, sky_uv.y));
+
+    color += vec3<f32>(star_light(p));
+    color += disc_light(p, aspect, sky.sun_disc, sky.sun_color);
+    color += disc_light(p, aspect, sky.moon_disc, sky.moon_color);
+
+    let cloud_noise = fbm((p - sky.clouds.zw * globals.time) * sky.clouds.y);
+    let cloud_amount = smoothstep(1.0 - sky.clouds.x, 1.0, cloud_noise) * sky.cloud_color.a;
+    color = mix(color, sky.cloud_color.rgb, cloud_amount);
+
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,31 @@
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+struct TilingBackgroundParams {
+    quad_size: vec2<f32>,   // Size of the covering quad in world units
+    tile_size: vec2<f32>,   // Size of one tile in world units
+    scroll: vec2<f32>,      // Accumulated scroll in world units
+    repeat_axes: vec2<f32>, // 1.0 when the axis repeats, 0.0 when it shows a single tile
+};
+
+@group(2) @binding(0) var<uniform> params: TilingBackgroundParams;
+@group(2) @binding(1) var tile_texture: texture_2d<f32>;
+@group(2) @binding(2) var tile_sampler: sampler; // Repeat address mode, see TilingBackgroundPlugin
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    // Position inside the quad in world units, centered on the quad, y up
+    let local = (mesh.uv - 0.5) * vec2<f32>(1.0, -1.0) * params.quad_size;
+    // Position inside the tiled plane, tile (0, 0) is centered on the quad
+    let plane = local - params.scroll;
+    let tile_uv = plane / params.tile_size * vec2<f32>(1.0, -1.0) + 0.5;
+
+    // Sample before masking so the sample stays in uniform control flow
+    let color = textureSample(tile_texture, tile_sampler, tile_uv);
+
+    // Axes that don't repeat only show the tile at the origin
+    let out_of_tile = (tile_uv < vec2<f32>(0.0)) | (tile_uv > vec2<f32>(1.0));
+    if any(out_of_tile & (params.repeat_axes < vec2<f32>(0.5))) {
+        return vec4<f32>(0.0);
+    }
+    return color;
+}
@@ -0,0 +1,54 @@
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+
+struct WeatherFogParams {
+    color: vec4<f32>,
+    quad_size: vec2<f32>, // Covered area in world units
+    offset: vec2<f32>,    // Accumulated wind drift in world units
+    density: f32,         // 0 (clear) to 1 (thick fog)
+};
+
+@group(2) @binding(0) var<uniform> fog: WeatherFogParams;
+
+// World units per noise cell of the largest fog banks
+const FOG_SCALE: f32 = 420.0;
+
+fn hash21(p: vec2<f32>) -> f32 {
+    var p3 = fract(vec3<f32>(p.xyx) * 0.1031);
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+fn value_noise(p: vec2<f32>) -> f32 {
+    let cell = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let a = hash21(cell);
+    let b = hash21(cell + vec2<f32>(1.0, 0.0));
+    let c = hash21(cell + vec2<f32>(0.0, 1.0));
+    let d = hash21(cell + vec2<f32>(1.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+fn fbm(p: vec2<f32>) -> f32 {
+    var value = 0.0;
+    var amplitude = 0.5;
+    var q = p;
+    for (var octave = 0; octave < 4; octave++) {
+        value += amplitude * value_noise(q);
+        q = q * 2.03 + vec2<f32>(17.0, 9.0);
+        amplitude *= 0.5;
+    }
+    return value;
+}
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    // World position on the quad, drifting against the wind
+    let world = (mesh.uv - 0.5) * vec2<f32>(1.0, -1.0) * fog.quad_size - fog.offset;
+    let banks = fbm(world / FOG_SCALE);
+    // Thicker towards the ground
+    let height = 1.0 - mesh.uv.y;
+    let ground = 1.0 - smoothstep(0.0, 0.8, height) * 0.6;
+    let amount = clamp(fog.density * (0.4 + banks) * ground, 0.0, 1.0);
+    return vec4<f32>(fog.color.rgb, amount * fog.color.a);
+}
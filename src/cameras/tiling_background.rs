@@ -0,0 +1,201 @@
+use bevy::asset::embedded_asset;
+use bevy::image::{ImageAddressMode, ImageSampler, ImageSamplerDescriptor};
+use bevy::prelude::*;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef, ShaderType};
+use bevy::sprite::{AlphaMode2d, Material2d, Material2dPlugin};
+
+use super::background_camera::{BackgroundTargets, FillBackgroundTarget};
+use super::camera_plugin::GameCamera;
+use super::layer_registry::{BACKGROUND_LAYER, LayerRegistry};
+
+const TILING_BACKGROUND_SHADER_PATH: &str =
+    "embedded://bevy_background_camera/cameras/shaders/tiling_background.wgsl";
+
+// An endlessly repeating background rendered on the background layer.
+// A single quad is kept covering the whole background target (see `FillBackgroundTarget`),
+// the repetition and the scrolling happen in the shader, so any viewport size is covered
+// by one entity.
+#[derive(Component, Clone, Debug)]
+pub struct TilingBackground {
+    pub image: Handle<Image>,
+    // Axes the image repeats on, a non-repeating axis shows a single strip through the origin
+    pub repeat: BVec2,
+    // Automatic scroll in background world units per second (clouds, starfields)
+    pub velocity: Vec2,
+    // Static offset of the tiling in background world units
+    pub offset: Vec2,
+    // How much of the game camera movement scrolls the tiling: 0 stays fixed on screen,
+    // 1 moves with the world, anything in between gives parallax
+    pub camera_factor: Vec2,
+    // Size of one tile in background world units, defaults to the native image size
+    pub tile_size: Option<Vec2>,
+}
+
+impl TilingBackground {
+    pub fn new(image: Handle<Image>) -> Self {
+        Self {
+            image,
+            repeat: BVec2::TRUE,
+            velocity: Vec2::ZERO,
+            offset: Vec2::ZERO,
+            camera_factor: Vec2::ZERO,
+            tile_size: None,
+        }
+    }
+}
+
+// Per-entity runtime state: the material driving the quad and the scroll accumulated so far
+#[derive(Component)]
+struct TilingBackgroundState {
+    material: Handle<TilingBackgroundMaterial>,
+    scrolled: Vec2,
+}
+
+shader_types! {
+    #[derive(Clone, Copy, Debug, Default, ShaderType)]
+    pub struct TilingBackgroundParams {
+        pub quad_size: Vec2,
+        pub tile_size: Vec2,
+        pub scroll: Vec2,
+        pub repeat_axes: Vec2,
+    }
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Clone, Debug)]
+pub struct TilingBackgroundMaterial {
+    #[uniform(0)]
+    pub params: TilingBackgroundParams,
+    #[texture(1)]
+    #[sampler(2)]
+    pub texture: Handle<Image>,
+}
+
+impl Material2d for TilingBackgroundMaterial {
+    fn fragment_shader() -> ShaderRef {
+        TILING_BACKGROUND_SHADER_PATH.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode2d {
+        AlphaMode2d::Blend
+    }
+}
+
+pub struct TilingBackgroundPlugin;
+
+impl Plugin for TilingBackgroundPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "shaders/tiling_background.wgsl");
+        app.add_plugins(Material2dPlugin::<TilingBackgroundMaterial>::default())
+            .add_systems(
+                Update,
+                (
+                    spawn_tiling_backgrounds,
+                    use_repeat_sampler,
+                    update_tiling_backgrounds,
+                )
+                    .chain(),
+            );
+    }
+}
+
+// Gives every new tiling background its quad, material and the background render layer
+fn spawn_tiling_backgrounds(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<TilingBackgroundMaterial>>,
+    layers: Res<LayerRegistry>,
+    tiling_backgrounds: Query<(Entity, &TilingBackground), Added<TilingBackground>>,
+) {
+    for (entity, tiling) in tiling_backgrounds.iter() {
+        let material = materials.add(TilingBackgroundMaterial {
+            params: TilingBackgroundParams::default(),
+            texture: tiling.image.clone(),
+        });
+        commands.entity(entity).insert((
+            Mesh2d(meshes.add(Rectangle::new(1.0, 1.0))),
+            MeshMaterial2d(material.clone()),
+            FillBackgroundTarget,
+            TilingBackgroundState {
+                material,
+                scrolled: Vec2::ZERO,
+            },
+        ));
+        // Kept when already set, e.g. to the incoming background of a transition
+        commands
+            .entity(entity)
+            .insert_if_new(layers.render_layers(BACKGROUND_LAYER));
+    }
+}
+
+// Switches tiled images to a repeating sampler once they are loaded
+fn use_repeat_sampler(
+    mut images: ResMut<Assets<Image>>,
+    tiling_backgrounds: Query<&TilingBackground>,
+) {
+    for tiling in tiling_backgrounds.iter() {
+        let Some(image) = images.get(&tiling.image) else {
+            continue;
+        };
+        let is_repeating = matches!(&image.sampler, ImageSampler::Descriptor(descriptor)
+            if matches!(descriptor.address_mode_u, ImageAddressMode::Repeat)
+                && matches!(descriptor.address_mode_v, ImageAddressMode::Repeat));
+        if is_repeating {
+            continue;
+        }
+        if let Some(image) = images.get_mut(&tiling.image) {
+            image.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+                label: Some("tiling_background_sampler".into()),
+                address_mode_u: ImageAddressMode::Repeat,
+                address_mode_v: ImageAddressMode::Repeat,
+                ..ImageSamplerDescriptor::linear()
+            });
+        }
+    }
+}
+
+// Advances the scroll and feeds the current quad and tile sizes to the material
+fn update_tiling_backgrounds(
+    time: Res<Time>,
+    images: Res<Assets<Image>>,
+    mut materials: ResMut<Assets<TilingBackgroundMaterial>>,
+    background_targets: BackgroundTargets,
+    game_camera: Query<&Transform, With<GameCamera>>,
+    mut tiling_backgrounds: Query<(&TilingBackground, &mut TilingBackgroundState)>,
+) {
+    let Some(target_size) = background_targets.main_size(&images) else {
+        return;
+    };
+    let camera_position = game_camera
+        .get_single()
+        .map(|transform| transform.translation.truncate())
+        .unwrap_or_default();
+
+    for (tiling, mut state) in tiling_backgrounds.iter_mut() {
+        let Some(image_size) = images.get(&tiling.image).map(|image| image.size()) else {
+            continue;
+        };
+        let tile_size = tiling.tile_size.unwrap_or(image_size.as_vec2());
+        // A whole tile scrolled on a repeating axis looks the same, so it is wrapped there to
+        // keep the offset small enough for f32 precision
+        let scrolled = state.scrolled + tiling.velocity * time.delta_secs();
+        state.scrolled = Vec2::select(
+            tiling.repeat & tile_size.cmpgt(Vec2::ZERO),
+            scrolled.rem_euclid(tile_size),
+            scrolled,
+        );
+
+        let Some(material) = materials.get_mut(&state.material) else {
+            continue;
+        };
+        material.params = TilingBackgroundParams {
+            quad_size: target_size.as_vec2(),
+            tile_size,
+            // Moving the camera right slides the content left, hence the negated camera term
+            scroll: tiling.offset + state.scrolled - camera_position * tiling.camera_factor,
+            repeat_axes: Vec2::new(
+                if tiling.repeat.x { 1.0 } else { 0.0 },
+                if tiling.repeat.y { 1.0 } else { 0.0 },
+            ),
+        };
+    }
+}
@@ -0,0 +1,154 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use super::background_camera::{BackgroundCamera, BackgroundSurface};
+use super::camera_plugin::{GameCamera, UpscaleCamera};
+use super::composite_pass::Letterbox;
+
+// Converts between positions in the primary window (logical pixels, (0, 0) top left, as
+// reported by `Window::cursor_position` and used by UI nodes) and the game and background
+// worlds. Handles what sits in between: the projection of the game camera (`ViewportScaling`
+// and zoom), the upscaled low resolution frame of `PixelPerfect`, the scale factor of the
+// window and the separate target of the background camera.
+// Positions use the logical camera transforms, camera shake does not move them. Positions on
+// letterbox bars (`ScalingPolicy::FitInside`, `PixelPerfect`) have no world point.
+// Only covers the usual single view setup: the primary window and one `GameCamera` rendering
+// to it (directly or through the `UpscaleCamera`). With more game cameras or windows every
+// conversion returns `None`, use the `Camera` methods of the camera in question instead.
+#[derive(SystemParam)]
+pub struct ViewCoordinates<'w, 's> {
+    primary_window: Query<'w, 's, (Entity, &'static Window), With<PrimaryWindow>>,
+    game_cameras: Query<
+        'w,
+        's,
+        (
+            &'static Camera,
+            &'static GlobalTransform,
+            Option<&'static Letterbox>,
+        ),
+        With<GameCamera>,
+    >,
+    upscale_cameras: Query<'w, 's, &'static Letterbox, With<UpscaleCamera>>,
+    background_cameras: Query<
+        'w,
+        's,
+        (
+            &'static Camera,
+            &'static GlobalTransform,
+            &'static BackgroundSurface,
+        ),
+        With<BackgroundCamera>,
+    >,
+}
+
+impl ViewCoordinates<'_, '_> {
+    pub fn cursor(&self) -> Option<Vec2> {
+        self.primary_window
+            .get_single()
+            .ok()
+            .and_then(|(_, window)| window.cursor_position())
+    }
+
+    pub fn cursor_to_game(&self) -> Option<Vec2> {
+        self.window_to_game(self.cursor()?)
+    }
+
+    // Background world point under the cursor, see `window_to_background`
+    pub fn cursor_to_background(&self, parallax: Vec2) -> Option<Vec2> {
+        self.window_to_background(self.cursor()?, parallax)
+    }
+
+    pub fn window_to_game(&self, position: Vec2) -> Option<Vec2> {
+        let (camera, transform, _) = self.game_cameras.get_single().ok()?;
+        let uv = self.window_to_game_uv(position)?;
+        camera
+            .viewport_to_world_2d(transform, uv * camera.logical_viewport_size()?)
+            .ok()
+    }
+
+    pub fn game_to_window(&self, world: Vec2) -> Option<Vec2> {
+        let (camera, transform, _) = self.game_cameras.get_single().ok()?;
+        let viewport = camera
+            .world_to_viewport(transform, world.extend(0.0))
+            .ok()?;
+        self.game_uv_to_window(viewport / camera.logical_viewport_size()?)
+    }
+
+    // Background world point at a window position. Background content scrolling with a
+    // `parallax` factor of the game camera movement (like `TilingBackground::camera_factor`)
+    // is offset accordingly, `Vec2::ZERO` gives the fixed background world.
+    pub fn window_to_background(&self, position: Vec2, parallax: Vec2) -> Option<Vec2> {
+        let (camera, transform) = self.background_camera()?;
+        let uv = self.window_to_game_uv(position)?;
+        let world = camera
+            .viewport_to_world_2d(transform, uv * camera.logical_viewport_size()?)
+            .ok()?;
+        Some(world + self.parallax_offset(parallax))
+    }
+
+    pub fn background_to_window(&self, world: Vec2, parallax: Vec2) -> Option<Vec2> {
+        let (camera, transform) = self.background_camera()?;
+        let world = world - self.parallax_offset(parallax);
+        let viewport = camera
+            .world_to_viewport(transform, world.extend(0.0))
+            .ok()?;
+        self.game_uv_to_window(viewport / camera.logical_viewport_size()?)
+    }
+
+    // Part of the window the game frame covers as fractions of the window, the letterbox of
+    // the `UpscaleCamera` with `PixelPerfect` and the whole window otherwise
+    fn game_rect(&self) -> Rect {
+        self.upscale_cameras
+            .get_single()
+            .map_or(Rect::new(0.0, 0.0, 1.0, 1.0), |letterbox| letterbox.rect)
+    }
+
+    // Part of the game target not covered by the bars of its own letterbox
+    // (`ScalingPolicy::FitInside`), the projection still spans the whole target
+    fn game_content_rect(&self) -> Rect {
+        self.game_cameras
+            .get_single()
+            .ok()
+            .and_then(|(_, _, letterbox)| letterbox)
+            .map_or(Rect::new(0.0, 0.0, 1.0, 1.0), |letterbox| letterbox.rect)
+    }
+
+    // Fraction of the game target at a window position, `None` on the letterbox bars
+    fn window_to_game_uv(&self, position: Vec2) -> Option<Vec2> {
+        let (_, window) = self.primary_window.get_single().ok()?;
+        let rect = self.game_rect();
+        let uv = (position / window.size() - rect.min) / rect.size();
+        self.game_content_rect().contains(uv).then_some(uv)
+    }
+
+    // Also converts points off the view or behind the letterbox bars, e.g. for edge markers
+    fn game_uv_to_window(&self, uv: Vec2) -> Option<Vec2> {
+        let (_, window) = self.primary_window.get_single().ok()?;
+        let rect = self.game_rect();
+        Some((rect.min + uv * rect.size()) * window.size())
+    }
+
+    // Background camera of the surface the game camera renders to
+    fn background_camera(&self) -> Option<(&Camera, &GlobalTransform)> {
+        let (game_camera, _, _) = self.game_cameras.get_single().ok()?;
+        let primary_window = self
+            .primary_window
+            .get_single()
+            .ok()
+            .map(|(entity, _)| entity);
+        let surface = BackgroundSurface::from_target(&game_camera.target, primary_window)?;
+        self.background_cameras
+            .iter()
+            .find(|(_, _, background_surface)| **background_surface == surface)
+            .map(|(camera, transform, _)| (camera, transform))
+    }
+
+    fn parallax_offset(&self, parallax: Vec2) -> Vec2 {
+        self.game_cameras
+            .get_single()
+            .map_or(Vec2::ZERO, |(_, transform, _)| {
+                transform.translation().truncate() * parallax
+            })
+    }
+}
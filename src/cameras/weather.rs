@@ -0,0 +1,410 @@
+use bevy::asset::embedded_asset;
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef, ShaderType};
+use bevy::sprite::{AlphaMode2d, Material2d, Material2dPlugin};
+use bevy::window::PrimaryWindow;
+
+use super::background_camera::{
+    BackgroundCamera, BackgroundLutSource, BackgroundProcessedRenderTarget, BackgroundRenderTarget,
+    BackgroundSurface, BackgroundTargets, FillBackgroundTarget, create_background_target_image,
+    despawn_orphaned_layer_cameras, resize_layer_targets,
+};
+use super::background_lut::identity_lut_image;
+use super::composite_pass::CompositeBackground;
+use super::layer_registry::{LayerRegistry, RegisterLayerExt, WEATHER_LAYER};
+
+const WEATHER_FOG_SHADER_PATH: &str =
+    "embedded://bevy_background_camera/cameras/shaders/weather_fog.wgsl";
+// Upper bound of live precipitation particles at full intensity
+const MAX_WEATHER_PARTICLES: usize = 1500;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Precipitation {
+    #[default]
+    None,
+    Rain,
+    Snow,
+}
+
+// Current weather, change it at runtime to drive the weather layer
+#[derive(Resource, Clone, Debug)]
+pub struct Weather {
+    pub precipitation: Precipitation,
+    // 0 (nothing) to 1 (downpour / blizzard)
+    pub intensity: f32,
+    // Horizontal push on particles and fog drift, in world units per second
+    pub wind: Vec2,
+    // 0 (clear) to 1 (thick fog)
+    pub fog_density: f32,
+    pub fog_color: Color,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Self {
+            precipitation: Precipitation::None,
+            intensity: 0.0,
+            wind: Vec2::ZERO,
+            fog_density: 0.0,
+            fog_color: Color::srgb(0.8, 0.82, 0.85),
+        }
+    }
+}
+
+// Color grade of the weather layer, independent from the background LUT.
+// Defaults to an identity LUT.
+#[derive(Resource, Clone, Debug)]
+pub struct WeatherGrade {
+    pub lut_texture: Handle<Image>,
+}
+
+// Marker component for the weather camera of a surface
+#[derive(Component)]
+pub struct WeatherCamera;
+
+// Graded weather layer a camera composites between its background and its own view, the
+// texture of the `CompositeSource::Weather` layers of its `CompositeStack`.
+// Kept in sync with the weather camera of the same surface by `link_weather_targets`.
+#[derive(Component, Clone)]
+pub struct WeatherCompositeSource {
+    pub handle: Handle<Image>,
+}
+
+// A single rain drop or snow flake
+#[derive(Component)]
+struct WeatherParticle {
+    velocity: Vec2,
+    // Phase of the sideways sway of snow flakes
+    sway: f32,
+}
+
+shader_types! {
+    #[derive(Clone, Copy, Debug, Default, ShaderType)]
+    pub struct WeatherFogParams {
+        pub color: Vec4,
+        pub quad_size: Vec2,
+        pub offset: Vec2,
+        pub density: f32,
+    }
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Clone, Debug)]
+pub struct WeatherFogMaterial {
+    #[uniform(0)]
+    pub params: WeatherFogParams,
+}
+
+impl Material2d for WeatherFogMaterial {
+    fn fragment_shader() -> ShaderRef {
+        WEATHER_FOG_SHADER_PATH.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode2d {
+        AlphaMode2d::Blend
+    }
+}
+
+// The fog quad and its accumulated drift
+#[derive(Component)]
+struct WeatherFog {
+    material: Handle<WeatherFogMaterial>,
+    offset: Vec2,
+}
+
+pub struct WeatherPlugin;
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        embedded_asset!(app, "shaders/weather_fog.wgsl");
+        app.register_layer(WEATHER_LAYER, 20, 2)
+            .add_plugins(Material2dPlugin::<WeatherFogMaterial>::default())
+            .init_resource::<Weather>()
+            .add_systems(Startup, setup_weather)
+            .add_systems(
+                Update,
+                (
+                    spawn_weather_cameras,
+                    despawn_orphaned_layer_cameras::<WeatherCamera>,
+                    resize_layer_targets::<WeatherCamera>,
+                    link_weather_targets,
+                    apply_weather_grade,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    spawn_weather_particles,
+                    move_weather_particles,
+                    update_weather_fog,
+                ),
+            );
+    }
+}
+
+fn setup_weather(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<WeatherFogMaterial>>,
+    layers: Res<LayerRegistry>,
+    grade: Option<Res<WeatherGrade>>,
+) {
+    if grade.is_none() {
+        commands.insert_resource(WeatherGrade {
+            lut_texture: images.add(identity_lut_image()),
+        });
+    }
+
+    let material = materials.add(WeatherFogMaterial {
+        params: WeatherFogParams::default(),
+    });
+    commands.spawn((
+        Mesh2d(meshes.add(Rectangle::new(1.0, 1.0))),
+        MeshMaterial2d(material.clone()),
+        // In front of the particles
+        Transform::from_xyz(0.0, 0.0, 10.0),
+        layers.render_layers(WEATHER_LAYER),
+        FillBackgroundTarget,
+        WeatherFog {
+            material,
+            offset: Vec2::ZERO,
+        },
+    ));
+}
+
+// Every background camera gets a weather camera for the same surface, rendering the weather
+// layer into its own target and grading it through the LUT pass with `WeatherGrade`
+fn spawn_weather_cameras(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    grade: Res<WeatherGrade>,
+    layers: Res<LayerRegistry>,
+    background_cameras: Query<
+        (&BackgroundSurface, &BackgroundRenderTarget),
+        Added<BackgroundCamera>,
+    >,
+) {
+    for (surface, background_target) in background_cameras.iter() {
+        let Some(size) = images
+            .get(&background_target.handle)
+            .map(|image| image.texture_descriptor.size)
+        else {
+            continue;
+        };
+        info!("Setting up weather camera for {:?}", surface);
+        let render_target_handle = images.add(create_background_target_image(
+            "weather_render_target",
+            size,
+        ));
+        let processed_target_handle = images.add(create_background_target_image(
+            "weather_processed_render_target",
+            size,
+        ));
+        commands.spawn((
+            Camera2d,
+            Camera {
+                order: layers.order(WEATHER_LAYER),
+                target: RenderTarget::Image(render_target_handle.clone()),
+                clear_color: ClearColorConfig::Custom(Color::srgba(0.0, 0.0, 0.0, 0.0)),
+                ..default()
+            },
+            layers.render_layers(WEATHER_LAYER),
+            WeatherCamera,
+            surface.clone(),
+            BackgroundLutSource {
+                lut_texture: grade.lut_texture.clone(),
+            },
+            BackgroundRenderTarget {
+                handle: render_target_handle,
+            },
+            BackgroundProcessedRenderTarget {
+                handle: processed_target_handle,
+            },
+        ));
+    }
+}
+
+// Points every camera opted in with `CompositeBackground` at the weather layer of the same
+// surface, and unlinks cameras that opted out
+fn link_weather_targets(
+    mut commands: Commands,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    weather_cameras: Query<
+        (&BackgroundSurface, &BackgroundProcessedRenderTarget),
+        With<WeatherCamera>,
+    >,
+    cameras: Query<(Entity, &Camera, Option<&WeatherCompositeSource>), With<CompositeBackground>>,
+    opted_out: Query<Entity, (With<WeatherCompositeSource>, Without<CompositeBackground>)>,
+) {
+    for entity in opted_out.iter() {
+        commands.entity(entity).remove::<WeatherCompositeSource>();
+    }
+
+    let primary_window = primary_window.get_single().ok();
+    for (entity, camera, current_source) in cameras.iter() {
+        let Some(camera_surface) = BackgroundSurface::from_target(&camera.target, primary_window)
+        else {
+            continue;
+        };
+        let processed = weather_cameras
+            .iter()
+            .find(|(surface, _)| **surface == camera_surface)
+            .map(|(_, processed)| processed);
+
+        match (processed, current_source) {
+            (Some(processed), Some(current)) if current.handle == processed.handle => {}
+            (Some(processed), _) => {
+                commands.entity(entity).insert(WeatherCompositeSource {
+                    handle: processed.handle.clone(),
+                });
+            }
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<WeatherCompositeSource>();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+fn apply_weather_grade(
+    grade: Res<WeatherGrade>,
+    mut weather_cameras: Query<&mut BackgroundLutSource, With<WeatherCamera>>,
+) {
+    if !grade.is_changed() {
+        return;
+    }
+    for mut lut_source in weather_cameras.iter_mut() {
+        lut_source.lut_texture = grade.lut_texture.clone();
+    }
+}
+
+// Emits new drops / flakes along the top edge (and the upwind side) of the visible area
+#[allow(clippy::too_many_arguments)]
+fn spawn_weather_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    weather: Res<Weather>,
+    images: Res<Assets<Image>>,
+    layers: Res<LayerRegistry>,
+    background_targets: BackgroundTargets,
+    particles: Query<(), With<WeatherParticle>>,
+    mut seed: Local<u32>,
+    mut pending: Local<f32>,
+) {
+    let (rate, fall_speed, size, color) = match weather.precipitation {
+        Precipitation::None => return,
+        Precipitation::Rain => (
+            900.0,
+            1400.0,
+            Vec2::new(2.0, 26.0),
+            Color::srgba(0.7, 0.8, 1.0, 0.55),
+        ),
+        Precipitation::Snow => (
+            160.0,
+            120.0,
+            Vec2::splat(5.0),
+            Color::srgba(1.0, 1.0, 1.0, 0.9),
+        ),
+    };
+    let Some(area) = background_targets
+        .main_size(&images)
+        .map(|size| size.as_vec2())
+    else {
+        return;
+    };
+
+    if *seed == 0 {
+        *seed = 0x9e37_79b9;
+    }
+    let intensity = weather.intensity.clamp(0.0, 1.0);
+    let budget = (MAX_WEATHER_PARTICLES as f32 * intensity) as usize;
+    *pending += rate * intensity * time.delta_secs();
+    while *pending >= 1.0 {
+        *pending -= 1.0;
+        if particles.iter().len() >= budget {
+            *pending = 0.0;
+            break;
+        }
+        // Cheap xorshift, the distribution doesn't need to be any good
+        let mut next = || {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 17;
+            *seed ^= *seed << 5;
+            (*seed as f32) / (u32::MAX as f32)
+        };
+        // Spawn over a wider strip so wind doesn't leave the upwind edge empty
+        let x = (next() - 0.5) * (area.x + weather.wind.x.abs() * 2.0) - weather.wind.x * 0.5;
+        let y = area.y * 0.5 + size.y;
+        let velocity = Vec2::new(
+            weather.wind.x,
+            -fall_speed * (0.8 + next() * 0.4) + weather.wind.y,
+        );
+        commands.spawn((
+            Sprite {
+                color,
+                custom_size: Some(size),
+                ..default()
+            },
+            Transform::from_xyz(x, y, 0.0)
+                .with_rotation(Quat::from_rotation_z(velocity.x.atan2(-velocity.y))),
+            layers.render_layers(WEATHER_LAYER),
+            WeatherParticle {
+                velocity,
+                sway: next() * std::f32::consts::TAU,
+            },
+        ));
+    }
+}
+
+fn move_weather_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    weather: Res<Weather>,
+    images: Res<Assets<Image>>,
+    background_targets: BackgroundTargets,
+    mut particles: Query<(Entity, &mut WeatherParticle, &mut Transform)>,
+) {
+    let area = background_targets
+        .main_size(&images)
+        .map(|size| size.as_vec2())
+        .unwrap_or(Vec2::splat(f32::MAX));
+    let dt = time.delta_secs();
+
+    for (entity, mut particle, mut transform) in particles.iter_mut() {
+        // Follow wind changes gradually
+        particle.velocity.x += (weather.wind.x - particle.velocity.x) * (dt * 2.0).min(1.0);
+        let mut step = particle.velocity * dt;
+        if weather.precipitation == Precipitation::Snow {
+            particle.sway += dt * 2.0;
+            step.x += particle.sway.sin() * 30.0 * dt;
+        }
+        transform.translation += step.extend(0.0);
+
+        let position = transform.translation.truncate();
+        let margin = area * 0.5 + Vec2::splat(64.0) + weather.wind.abs();
+        if position.y < -margin.y || position.x.abs() > margin.x {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn update_weather_fog(
+    time: Res<Time>,
+    weather: Res<Weather>,
+    mut materials: ResMut<Assets<WeatherFogMaterial>>,
+    mut fogs: Query<(&mut WeatherFog, &Transform)>,
+) {
+    for (mut fog, transform) in fogs.iter_mut() {
+        fog.offset += weather.wind * time.delta_secs();
+        if let Some(material) = materials.get_mut(&fog.material) {
+            material.params = WeatherFogParams {
+                color: LinearRgba::from(weather.fog_color).to_vec4(),
+                quad_size: transform.scale.truncate(),
+                offset: fog.offset,
+                density: weather.fog_density.clamp(0.0, 1.0),
+            };
+        }
+    }
+}
@@ -0,0 +1,40 @@
+// Layered 2D cameras for bevy: a graded background (and weather) layer rendered by its own
+// cameras and composited under the game camera, with scaling, letterboxing and camera
+// controls on top. Add `BackgroundCameraPlugins` and put content on the layers of the
+// `LayerRegistry`, see examples/demo.rs.
+// The shaders and the default background LUT are embedded, nothing has to be copied into the
+// assets of your app.
+pub mod cameras;
+
+use bevy::app::{PluginGroup, PluginGroupBuilder};
+
+use cameras::{
+    animated_background::AnimatedBackgroundPlugin, background_camera::BackgroundCameraPlugin,
+    background_fit::BackgroundFitPlugin, background_lut::BackgroundLutPlugin,
+    background_transition::BackgroundTransitionPlugin, camera_plugin::CameraPlugin,
+    camera_shake::CameraShakePlugin, composite_pass::CompositePlugin, minimap::MinimapPlugin,
+    procedural_sky::ProceduralSkyPlugin, tiling_background::TilingBackgroundPlugin,
+    weather::WeatherPlugin,
+};
+
+// Every plugin of the crate. Resources changing how the cameras are set up (`RenderOutput`,
+// `HdrComposite`, `PixelPerfect`, `ViewportScaling`) are read at startup, insert them before.
+pub struct BackgroundCameraPlugins;
+
+impl PluginGroup for BackgroundCameraPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(BackgroundLutPlugin)
+            .add(CameraPlugin)
+            .add(CompositePlugin)
+            .add(BackgroundCameraPlugin)
+            .add(BackgroundFitPlugin)
+            .add(TilingBackgroundPlugin)
+            .add(AnimatedBackgroundPlugin)
+            .add(ProceduralSkyPlugin)
+            .add(WeatherPlugin)
+            .add(BackgroundTransitionPlugin)
+            .add(CameraShakePlugin)
+            .add(MinimapPlugin)
+    }
+}